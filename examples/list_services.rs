@@ -0,0 +1,37 @@
+// List all win32 services installed on the system.
+//
+// Run in command prompt:
+//
+// `list_services.exe`
+
+#[cfg(windows)]
+fn main() -> windows_service::Result<()> {
+    use windows_service::{
+        service::ServiceType,
+        service_manager::{
+            sorted_by_name, ServiceManager, ServiceManagerAccess, ServiceStateFilter,
+        },
+    };
+
+    let manager_access = ServiceManagerAccess::CONNECT | ServiceManagerAccess::ENUMERATE_SERVICE;
+    let service_manager = ServiceManager::local_computer(None::<&str>, manager_access)?;
+
+    let services = service_manager.enumerate_services(
+        ServiceType::OWN_PROCESS | ServiceType::SHARE_PROCESS,
+        ServiceStateFilter::All,
+    )?;
+
+    for service in sorted_by_name(services) {
+        println!(
+            "{:<40} {:?}",
+            service.name.to_string_lossy(),
+            service.status.current_state
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn main() {
+    panic!("This program is only intended to run on Windows.");
+}
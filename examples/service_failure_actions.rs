@@ -70,6 +70,7 @@ fn main() -> windows_service::Result<()> {
     println!("Query failure actions");
     let updated_failure_actions = service.get_failure_actions()?;
     println!("{:#?}", updated_failure_actions);
+    println!("Summary: {}", updated_failure_actions.summary());
 
     println!("Enable failure actions on non-crash failures");
     service.set_failure_actions_on_non_crash_failures(true)?;
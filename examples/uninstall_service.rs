@@ -21,7 +21,7 @@ fn main() -> windows_service::Result<()> {
     // However, it will not be deleted from the database until it is stopped and all open handles to it are closed.
     service.delete()?;
     // Our handle to it is not closed yet. So we can still query it.
-    if service.query_status()?.current_state != ServiceState::Stopped {
+    if service.state()? != ServiceState::Stopped {
         // If the service cannot be stopped, it will be deleted when the system restarts.
         service.stop()?;
     }
@@ -176,6 +176,8 @@
 
 #![cfg(windows)]
 
+use windows_sys::Win32::Foundation::ERROR_ACCESS_DENIED;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -189,6 +191,26 @@ pub enum Error {
     ArgumentHasNulByte(&'static str),
     /// An argument array contains a nul byte in element at the given index
     ArgumentArrayElementHasNulByte(&'static str, usize),
+    /// Per-user service type combined with flags that per-user services do not support
+    InvalidUserServiceType,
+    /// The system rejected the creation of a per-user service
+    UserServiceNotSupported(std::io::Error),
+    /// A service status is not valid for the service it is reported for
+    InvalidServiceStatus(&'static str),
+    /// Timed out waiting for a service to change state, carries the last observed state
+    Timeout(service::ServiceState),
+    /// The service moved away from the state that was being waited for, for instance it stopped
+    /// while starting. Carries the observed status, including the exit code
+    UnexpectedState(service::ServiceStatus),
+    /// The service executable path is not absolute
+    RelativeExecutablePath(std::path::PathBuf),
+    /// The service dependencies form a cycle, carries the services on the cycle
+    DependencyCycle(Vec<std::ffi::OsString>),
+    /// Access denied in winapi call.
+    ///
+    /// This usually means that the process is not running elevated, or that the service manager
+    /// or service was opened without the access rights required for the operation.
+    AccessDenied(std::io::Error),
     /// IO error in winapi call
     Winapi(std::io::Error),
 }
@@ -197,6 +219,8 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::ParseValue(_, e) => Some(e),
+            Self::UserServiceNotSupported(e) => Some(e),
+            Self::AccessDenied(e) => Some(e),
             Self::Winapi(e) => Some(e),
             _ => None,
         }
@@ -216,11 +240,59 @@ impl std::fmt::Display for Error {
                 "{} contains a nul byte in element at {} index",
                 name, index
             ),
+            Self::InvalidUserServiceType => write!(
+                f,
+                "per-user services cannot be drivers or interactive services"
+            ),
+            Self::UserServiceNotSupported(_) => write!(
+                f,
+                "failed to create per-user service, the system may not support per-user services"
+            ),
+            Self::InvalidServiceStatus(reason) => write!(f, "invalid service status: {}", reason),
+            Self::Timeout(state) => write!(
+                f,
+                "timed out waiting for the service, last observed state: {:?}",
+                state
+            ),
+            Self::UnexpectedState(status) => write!(
+                f,
+                "the service unexpectedly reached state {:?}, exit code: {:?}",
+                status.current_state, status.exit_code
+            ),
+            Self::RelativeExecutablePath(path) => {
+                write!(f, "executable path is not absolute: {}", path.display())
+            }
+            Self::DependencyCycle(names) => {
+                write!(f, "service dependencies form a cycle: ")?;
+                for (i, name) in names.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " -> ")?;
+                    }
+                    write!(f, "{}", name.to_string_lossy())?;
+                }
+                Ok(())
+            }
+            Self::AccessDenied(_) => write!(
+                f,
+                "access denied, make sure that the process is running elevated and that the \
+                 required access rights were requested"
+            ),
             Self::Winapi(_) => write!(f, "IO error in winapi call"),
         }
     }
 }
 
+impl Error {
+    /// Wrap an error returned from a winapi call, singling out `ERROR_ACCESS_DENIED`.
+    pub(crate) fn winapi(error: std::io::Error) -> Self {
+        if error.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32) {
+            Self::AccessDenied(error)
+        } else {
+            Self::Winapi(error)
+        }
+    }
+}
+
 mod sc_handle;
 pub mod service;
 pub mod service_control_handler;
@@ -229,4 +301,6 @@ pub mod service_manager;
 pub mod service_dispatcher;
 
 mod double_nul_terminated;
+#[cfg(feature = "serde")]
+mod serde_os_str;
 mod shell_escape;
@@ -14,6 +14,10 @@ impl ScHandle {
     }
 }
 
+// Service control manager and service handles can be used from any thread.
+unsafe impl Send for ScHandle {}
+unsafe impl Sync for ScHandle {}
+
 impl Drop for ScHandle {
     fn drop(&mut self) {
         unsafe { Services::CloseServiceHandle(self.0) };
@@ -0,0 +1,70 @@
+//! Serde helpers that represent `OsString` values as strings.
+//!
+//! Serde represents `OsString` as a platform tagged sequence of code units by default, which is
+//! impractical in manifests that are written by hand. These helpers use plain strings instead
+//! and fail to serialize strings that are not valid Unicode.
+
+use std::ffi::OsString;
+
+use serde::{ser, Deserialize, Deserializer, Serializer};
+
+pub(crate) fn serialize<S: Serializer>(value: &OsString, serializer: S) -> Result<S::Ok, S::Error> {
+    let value = value
+        .to_str()
+        .ok_or_else(|| ser::Error::custom("string is not valid unicode"))?;
+    serializer.serialize_str(value)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<OsString, D::Error> {
+    String::deserialize(deserializer).map(OsString::from)
+}
+
+pub(crate) mod option {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        value: &Option<OsString>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => {
+                let value = value
+                    .to_str()
+                    .ok_or_else(|| ser::Error::custom("string is not valid unicode"))?;
+                serializer.serialize_some(value)
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<OsString>, D::Error> {
+        Option::<String>::deserialize(deserializer).map(|value| value.map(OsString::from))
+    }
+}
+
+pub(crate) mod vec {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        values: &[OsString],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let values = values
+            .iter()
+            .map(|value| value.to_str())
+            .collect::<Option<Vec<&str>>>()
+            .ok_or_else(|| ser::Error::custom("string is not valid unicode"))?;
+        serializer.collect_seq(values)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<OsString>, D::Error> {
+        Vec::<String>::deserialize(deserializer)
+            .map(|values| values.into_iter().map(OsString::from).collect())
+    }
+}
@@ -2,18 +2,28 @@ use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
 use std::os::raw::c_void;
 use std::os::windows::ffi::{OsStrExt, OsStringExt};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::ptr;
-use std::time::Duration;
-use std::{io, mem};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{io, mem, slice};
 
 use widestring::{error::ContainsNul, WideCStr, WideCString, WideString};
 use windows_sys::{
     core::GUID,
     Win32::{
-        Foundation::{ERROR_SERVICE_SPECIFIC_ERROR, NO_ERROR},
+        Foundation::{
+            CloseHandle, ERROR_FILE_NOT_FOUND, ERROR_INSUFFICIENT_BUFFER, ERROR_INVALID_LEVEL,
+            ERROR_NONE_MAPPED, ERROR_NOT_SUPPORTED, ERROR_SERVICE_CANNOT_ACCEPT_CTRL,
+            ERROR_SERVICE_NOT_ACTIVE, ERROR_SERVICE_SPECIFIC_ERROR, FILETIME, NO_ERROR,
+        },
+        Security,
         Storage::FileSystem,
-        System::{Power, RemoteDesktop, Services, SystemServices, Threading::INFINITE},
+        System::{
+            Power, RemoteDesktop, Services, SystemServices,
+            Threading::{self, INFINITE},
+        },
         UI::WindowsAndMessaging,
     },
 };
@@ -25,6 +35,7 @@ use crate::{double_nul_terminated, Error};
 bitflags::bitflags! {
     /// Enum describing the types of Windows services.
     #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct ServiceType: u32 {
         /// File system driver service.
         const FILE_SYSTEM_DRIVER = Services::SERVICE_FILE_SYSTEM_DRIVER;
@@ -46,7 +57,84 @@ bitflags::bitflags! {
 
         /// The service can be interactive.
         const INTERACTIVE_PROCESS = SystemServices::SERVICE_INTERACTIVE_PROCESS;
+
+        /// The service is an instance of a per-user service, created by the system from a
+        /// [`ServiceType::USER_OWN_PROCESS`] or [`ServiceType::USER_SHARE_PROCESS`] template
+        /// when a user logs on. Services are never created with this flag directly.
+        const USER_SERVICE_INSTANCE = SystemServices::SERVICE_USERSERVICE_INSTANCE;
+    }
+}
+
+impl ServiceType {
+    /// Returns `true` if this is a per-user service type, i.e either
+    /// [`ServiceType::USER_OWN_PROCESS`] or [`ServiceType::USER_SHARE_PROCESS`].
+    pub fn is_user_service(&self) -> bool {
+        self.bits() & SystemServices::SERVICE_USER_SERVICE != 0
+    }
+
+    /// Returns `true` if this is the type of a per-user service instance, as opposed to the
+    /// template it was created from.
+    ///
+    /// See [`user_service_template_name`] for finding the template of an instance.
+    pub fn is_user_service_instance(&self) -> bool {
+        self.is_user_service() && self.contains(ServiceType::USER_SERVICE_INSTANCE)
+    }
+
+    /// Returns `true` if this is a kernel or file system driver service type.
+    pub(crate) fn is_driver(&self) -> bool {
+        self.intersects(ServiceType::KERNEL_DRIVER | ServiceType::FILE_SYSTEM_DRIVER)
+    }
+
+    /// Returns the broad category of the service type, as presented by the services snap-in.
+    pub fn category(&self) -> ServiceCategory {
+        if self.is_driver() {
+            ServiceCategory::Driver
+        } else if self.is_user_service() {
+            ServiceCategory::UserService
+        } else if self.contains(ServiceType::SHARE_PROCESS) {
+            ServiceCategory::Win32SharedProcess
+        } else if self.contains(ServiceType::OWN_PROCESS) {
+            ServiceCategory::Win32OwnProcess
+        } else {
+            ServiceCategory::Unknown
+        }
+    }
+}
+
+/// Returns the name of the per-user service template that the instance `instance_name` was
+/// created from.
+///
+/// The system names each instance of a per-user service after its template, followed by an
+/// underscore and a hexadecimal identifier of the logon session, for example `MyService_3a5f1`
+/// for the template `MyService`. Returns `None` if `instance_name` does not have this form.
+/// Use [`ServiceType::is_user_service_instance`] to check that a service actually is an
+/// instance, since a regular service may have a name of the same form.
+pub fn user_service_template_name(instance_name: impl AsRef<OsStr>) -> Option<OsString> {
+    let wide: Vec<u16> = instance_name.as_ref().encode_wide().collect();
+    let separator = wide.iter().rposition(|c| *c == b'_' as u16)?;
+    let suffix = &wide[separator + 1..];
+    let is_hex_digit = |c: &u16| u8::try_from(*c).map_or(false, |c| c.is_ascii_hexdigit());
+    if separator == 0 || suffix.is_empty() || !suffix.iter().all(is_hex_digit) {
+        return None;
     }
+    Some(OsString::from_wide(&wide[..separator]))
+}
+
+/// Enum describing the broad categories of service types.
+///
+/// See [`ServiceType::category`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServiceCategory {
+    /// Kernel or file system driver.
+    Driver,
+    /// Service that runs in its own process.
+    Win32OwnProcess,
+    /// Service that shares a process with other services.
+    Win32SharedProcess,
+    /// Per-user service, running either in its own process or in a shared process.
+    UserService,
+    /// Service type that does not belong to any known category.
+    Unknown,
 }
 
 bitflags::bitflags! {
@@ -94,8 +182,16 @@ bitflags::bitflags! {
     }
 }
 
+impl std::fmt::Display for ServiceAccess {
+    /// Formats the set flags by name, for instance `QUERY_STATUS | START`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        bitflags::parser::to_writer(self, f)
+    }
+}
+
 /// Enum describing the start options for windows services.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u32)]
 pub enum ServiceStartType {
     /// Autostart on system startup
@@ -133,35 +229,67 @@ impl ServiceStartType {
 ///
 /// See <https://msdn.microsoft.com/en-us/library/windows/desktop/ms682450(v=vs.85).aspx>
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(u32)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ServiceErrorControl {
-    Critical = Services::SERVICE_ERROR_CRITICAL,
-    Ignore = Services::SERVICE_ERROR_IGNORE,
-    Normal = Services::SERVICE_ERROR_NORMAL,
-    Severe = Services::SERVICE_ERROR_SEVERE,
+    Critical,
+    Ignore,
+    Normal,
+    Severe,
+    /// A value that is not defined by the system. Some vendor services are configured with
+    /// such values, which are preserved so that they can be passed back to the system.
+    ///
+    /// Values converted with [`From<u32>`] never hold a defined value in this variant. An
+    /// `Unknown` constructed with a defined value, for instance `Unknown(SERVICE_ERROR_NORMAL)`,
+    /// compares unequal to the corresponding variant, use [`ServiceErrorControl::normalize`] to
+    /// convert it.
+    Unknown(u32),
 }
 
 impl ServiceErrorControl {
     pub fn to_raw(&self) -> u32 {
-        *self as u32
+        match *self {
+            ServiceErrorControl::Critical => Services::SERVICE_ERROR_CRITICAL,
+            ServiceErrorControl::Ignore => Services::SERVICE_ERROR_IGNORE,
+            ServiceErrorControl::Normal => Services::SERVICE_ERROR_NORMAL,
+            ServiceErrorControl::Severe => Services::SERVICE_ERROR_SEVERE,
+            ServiceErrorControl::Unknown(raw) => raw,
+        }
     }
 
+    /// Convert a raw error control value, returning [`ServiceErrorControl::Unknown`] for values
+    /// that are not defined by the system. This never fails.
+    #[deprecated(note = "use `ServiceErrorControl::from` instead")]
     pub fn from_raw(raw: u32) -> Result<ServiceErrorControl, ParseRawError> {
+        Ok(ServiceErrorControl::from(raw))
+    }
+
+    /// Returns the variant for a defined value held in [`ServiceErrorControl::Unknown`], and
+    /// `self` otherwise.
+    pub fn normalize(self) -> ServiceErrorControl {
+        ServiceErrorControl::from(self.to_raw())
+    }
+}
+
+impl From<u32> for ServiceErrorControl {
+    /// Convert a raw error control value, returning [`ServiceErrorControl::Unknown`] for values
+    /// that are not defined by the system.
+    fn from(raw: u32) -> Self {
         match raw {
-            x if x == ServiceErrorControl::Critical.to_raw() => Ok(ServiceErrorControl::Critical),
-            x if x == ServiceErrorControl::Ignore.to_raw() => Ok(ServiceErrorControl::Ignore),
-            x if x == ServiceErrorControl::Normal.to_raw() => Ok(ServiceErrorControl::Normal),
-            x if x == ServiceErrorControl::Severe.to_raw() => Ok(ServiceErrorControl::Severe),
-            _ => Err(ParseRawError::InvalidInteger(raw)),
+            Services::SERVICE_ERROR_CRITICAL => ServiceErrorControl::Critical,
+            Services::SERVICE_ERROR_IGNORE => ServiceErrorControl::Ignore,
+            Services::SERVICE_ERROR_NORMAL => ServiceErrorControl::Normal,
+            Services::SERVICE_ERROR_SEVERE => ServiceErrorControl::Severe,
+            _ => ServiceErrorControl::Unknown(raw),
         }
     }
 }
 
 /// Service dependency descriptor
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ServiceDependency {
-    Service(OsString),
-    Group(OsString),
+    Service(#[cfg_attr(feature = "serde", serde(with = "crate::serde_os_str"))] OsString),
+    Group(#[cfg_attr(feature = "serde", serde(with = "crate::serde_os_str"))] OsString),
 }
 
 impl ServiceDependency {
@@ -197,12 +325,15 @@ impl ServiceDependency {
 
 /// Enum describing the types of actions that the service control manager can perform.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(i32)]
 pub enum ServiceActionType {
     None = Services::SC_ACTION_NONE,
     Reboot = Services::SC_ACTION_REBOOT,
     Restart = Services::SC_ACTION_RESTART,
     RunCommand = Services::SC_ACTION_RUN_COMMAND,
+    /// Restart the service within the same process. Supported on newer versions of Windows.
+    OwnRestart = Services::SC_ACTION_OWN_RESTART,
 }
 
 impl ServiceActionType {
@@ -216,15 +347,29 @@ impl ServiceActionType {
             x if x == ServiceActionType::Reboot.to_raw() => Ok(ServiceActionType::Reboot),
             x if x == ServiceActionType::Restart.to_raw() => Ok(ServiceActionType::Restart),
             x if x == ServiceActionType::RunCommand.to_raw() => Ok(ServiceActionType::RunCommand),
+            x if x == ServiceActionType::OwnRestart.to_raw() => Ok(ServiceActionType::OwnRestart),
             _ => Err(ParseRawError::InvalidIntegerSigned(raw)),
         }
     }
 }
 
+impl std::fmt::Display for ServiceActionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ServiceActionType::None => "No action",
+            ServiceActionType::Reboot => "Reboot",
+            ServiceActionType::Restart => "Restart",
+            ServiceActionType::RunCommand => "Run command",
+            ServiceActionType::OwnRestart => "Restart in process",
+        })
+    }
+}
+
 /// Represents an action that the service control manager can perform.
 ///
 /// See <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-sc_action>
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServiceAction {
     /// The action to be performed.
     pub action_type: ServiceActionType,
@@ -255,6 +400,47 @@ impl ServiceAction {
     }
 }
 
+impl std::fmt::Display for ServiceAction {
+    /// Formats the action for presenting to users, for instance `Restart after 5 seconds`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.action_type {
+            // The delay is meaningless when no action is taken.
+            ServiceActionType::None => write!(f, "{}", self.action_type),
+            _ if self.delay.is_zero() => write!(f, "{} immediately", self.action_type),
+            _ => write!(
+                f,
+                "{} after {}",
+                self.action_type,
+                HumanDuration(self.delay)
+            ),
+        }
+    }
+}
+
+/// Formats a duration in the largest unit that represents it exactly, for instance `1 day` or
+/// `90 seconds`.
+struct HumanDuration(Duration);
+
+impl std::fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const UNITS: &[(u128, &str)] = &[
+            (24 * 60 * 60 * 1000, "day"),
+            (60 * 60 * 1000, "hour"),
+            (60 * 1000, "minute"),
+            (1000, "second"),
+            (1, "millisecond"),
+        ];
+        let millis = self.0.as_millis();
+        let (unit_millis, unit) = UNITS
+            .iter()
+            .copied()
+            .find(|(unit_millis, _)| millis % unit_millis == 0)
+            .unwrap_or((1, "millisecond"));
+        let count = millis / unit_millis;
+        write!(f, "{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+    }
+}
+
 /// A enum that represents the reset period for the failure counter.
 ///
 /// # Panics
@@ -262,6 +448,7 @@ impl ServiceAction {
 /// Converting this to the FFI form will panic if the period is too large to fit as seconds in a
 /// `u32`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ServiceFailureResetPeriod {
     Never,
     After(Duration),
@@ -290,6 +477,7 @@ impl ServiceFailureResetPeriod {
 /// Please refer to MSDN for more info:\
 /// <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-_service_failure_actionsw>
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServiceFailureActions {
     /// The time after which to reset the failure count to zero if there are no failures, in
     /// seconds.
@@ -300,6 +488,10 @@ pub struct ServiceFailureActions {
     ///
     /// If this value is `None`, the reboot message is unchanged.
     /// If the value is an empty string, the reboot message is deleted and no message is broadcast.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_os_str::option")
+    )]
     pub reboot_msg: Option<OsString>,
 
     /// The command line to execute in response to the `SC_ACTION_RUN_COMMAND` service controller
@@ -307,6 +499,10 @@ pub struct ServiceFailureActions {
     ///
     /// If this value is `None`, the command is unchanged. If the value is an empty string, the
     /// command is deleted and no program is run when the service fails.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_os_str::option")
+    )]
     pub command: Option<OsString>,
 
     /// The array of actions to perform.
@@ -357,15 +553,59 @@ impl ServiceFailureActions {
             actions,
         })
     }
+
+    /// Summarize the actions for presenting to users, for instance
+    /// `Restart x2 then run command; reset after 1 day`.
+    ///
+    /// Consecutive actions of the same type are collapsed and delays are left out. Use the
+    /// [`Display`](std::fmt::Display) implementation of [`ServiceAction`] to present each action
+    /// in detail.
+    pub fn summary(&self) -> String {
+        let actions = match &self.actions {
+            Some(actions) if !actions.is_empty() => actions,
+            _ => return String::from("No actions"),
+        };
+
+        let mut groups: Vec<(ServiceActionType, usize)> = Vec::new();
+        for action in actions {
+            match groups.last_mut() {
+                Some((action_type, count)) if *action_type == action.action_type => *count += 1,
+                _ => groups.push((action.action_type, 1)),
+            }
+        }
+
+        let mut summary = String::new();
+        for (i, (action_type, count)) in groups.into_iter().enumerate() {
+            if i == 0 {
+                summary.push_str(&action_type.to_string());
+            } else {
+                summary.push_str(" then ");
+                summary.push_str(&action_type.to_string().to_lowercase());
+            }
+            if count > 1 {
+                summary.push_str(&format!(" x{}", count));
+            }
+        }
+        match self.reset_period {
+            ServiceFailureResetPeriod::Never => summary.push_str("; never reset"),
+            ServiceFailureResetPeriod::After(period) => {
+                summary.push_str(&format!("; reset after {}", HumanDuration(period)))
+            }
+        }
+        summary
+    }
 }
 
 /// A struct that describes the service.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServiceInfo {
     /// Service name
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_os_str"))]
     pub name: OsString,
 
     /// User-friendly service name
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_os_str"))]
     pub display_name: OsString,
 
     /// The service type
@@ -378,25 +618,407 @@ pub struct ServiceInfo {
     pub error_control: ServiceErrorControl,
 
     /// Path to the service binary
+    ///
+    /// The path is resolved by the service control manager, which is a native process. Paths
+    /// under `System32` therefore refer to the native `System32` directory, even when the
+    /// service is installed from a 32-bit process. See
+    /// [`ServiceInfo::with_native_system32_path`] for building the path from a 32-bit process.
     pub executable_path: PathBuf,
 
     /// Launch arguments passed to `main` when system starts the service.
     /// This is not the same as arguments passed to `service_main`.
+    #[cfg_attr(feature = "serde", serde(default, with = "crate::serde_os_str::vec"))]
     pub launch_arguments: Vec<OsString>,
 
     /// Service dependencies
+    #[cfg_attr(feature = "serde", serde(default))]
     pub dependencies: Vec<ServiceDependency>,
 
     /// Account to use for running the service.
     /// for example: NT Authority\System.
     /// use `None` to run as LocalSystem.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_os_str::option")
+    )]
     pub account_name: Option<OsString>,
 
     /// Account password.
     /// For system accounts this should normally be `None`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_os_str::option")
+    )]
     pub account_password: Option<OsString>,
 }
 
+impl ServiceInfo {
+    /// Check the service description for common install mistakes before creating the service.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::RelativeExecutablePath`] if [`ServiceInfo::executable_path`] is not
+    /// absolute. The service control manager launches services with `C:\Windows\System32` as
+    /// the working directory, so a relative path typically fails at runtime with
+    /// `ERROR_SERVICE_REQUEST_TIMEOUT` (1053) or `ERROR_PROCESS_ABORTED` (1067). Drivers are
+    /// exempt since their paths are conventionally relative to the system root.
+    ///
+    /// [`Error::RelativeExecutablePath`]: crate::Error::RelativeExecutablePath
+    pub fn validate(&self) -> crate::Result<()> {
+        if !self.service_type.is_driver() && !self.executable_path.is_absolute() {
+            return Err(Error::RelativeExecutablePath(self.executable_path.clone()));
+        }
+        Ok(())
+    }
+
+    /// Rewrite an executable path under `%SystemRoot%\Sysnative` to `%SystemRoot%\System32`.
+    ///
+    /// A 32-bit installer on 64-bit Windows has its accesses to `System32` redirected to
+    /// `SysWOW64`, so it has to go through the `Sysnative` alias to locate, copy or inspect a
+    /// native binary. The service control manager is a native process however, and `Sysnative`
+    /// does not exist for it. This function lets the installer build the executable path from the
+    /// same `Sysnative` path it used locally, while registering the service with the path the
+    /// service control manager resolves to the native binary.
+    ///
+    /// The path is left unchanged if it is not within `%SystemRoot%\Sysnative`.
+    pub fn with_native_system32_path(mut self) -> Self {
+        if let Some(system_root) = std::env::var_os("SystemRoot") {
+            if let Some(path) = replace_system_directory(
+                &self.executable_path,
+                &system_root,
+                "Sysnative",
+                "System32",
+            ) {
+                self.executable_path = path;
+            }
+        }
+        self
+    }
+
+    /// Compare the service description with the config stored by the service control manager.
+    ///
+    /// Returns the differences between the desired and the actual config, or an empty vector if
+    /// they match. The executable path and launch arguments are compared in the escaped form
+    /// passed to the system when creating the service. A missing account name is treated as
+    /// `LocalSystem` and account names are compared ignoring ASCII case. The account is not
+    /// compared for drivers.
+    ///
+    /// See [`Service::verify_matches`] to query the config and compare it in one step.
+    pub fn differences(&self, config: &ServiceConfig) -> crate::Result<Vec<ConfigDifference>> {
+        let mut differences = Vec::new();
+
+        if self.service_type != config.service_type {
+            differences.push(ConfigDifference::ServiceType {
+                expected: self.service_type,
+                actual: config.service_type,
+            });
+        }
+        if self.start_type != config.start_type {
+            differences.push(ConfigDifference::StartType {
+                expected: self.start_type,
+                actual: config.start_type,
+            });
+        }
+        if self.error_control != config.error_control {
+            differences.push(ConfigDifference::ErrorControl {
+                expected: self.error_control,
+                actual: config.error_control,
+            });
+        }
+
+        let launch_command = RawServiceInfo::new(self)?.launch_command.to_os_string();
+        if launch_command != config.executable_path.as_os_str() {
+            differences.push(ConfigDifference::LaunchCommand {
+                expected: launch_command,
+                actual: config.executable_path.clone().into_os_string(),
+            });
+        }
+
+        if self.dependencies != config.dependencies {
+            differences.push(ConfigDifference::Dependencies {
+                expected: self.dependencies.clone(),
+                actual: config.dependencies.clone(),
+            });
+        }
+
+        if !self.service_type.is_driver() {
+            let expected = self
+                .account_name
+                .clone()
+                .unwrap_or_else(|| OsString::from("LocalSystem"));
+            let matches = config
+                .account_name
+                .as_ref()
+                .map_or(false, |actual| eq_ignore_ascii_case_wide(&expected, actual));
+            if !matches {
+                differences.push(ConfigDifference::AccountName {
+                    expected,
+                    actual: config.account_name.clone(),
+                });
+            }
+        }
+
+        if self.display_name != config.display_name {
+            differences.push(ConfigDifference::DisplayName {
+                expected: self.display_name.clone(),
+                actual: config.display_name.clone(),
+            });
+        }
+
+        Ok(differences)
+    }
+}
+
+/// A declarative description of a service, used to create or update it with
+/// [`ServiceManager::apply_manifest`].
+///
+/// This extends [`ServiceInfo`] with the optional configuration that is set separately from the
+/// basic configuration. Optional parts that are `None` are left unchanged.
+///
+/// With the `serde` feature enabled the manifest can be deserialized, for instance from a TOML or
+/// JSON file. The [`ServiceInfo`] fields are flattened into the manifest and strings are
+/// represented as plain strings.
+///
+/// [`ServiceManager::apply_manifest`]: crate::service_manager::ServiceManager::apply_manifest
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceManifest {
+    /// The basic service configuration.
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub info: ServiceInfo,
+
+    /// The service description.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_os_str::option")
+    )]
+    pub description: Option<OsString>,
+
+    /// Whether an auto-start service is started after the other auto-start services.
+    pub delayed_auto_start: Option<bool>,
+
+    /// How the service SID is added to the service process token.
+    pub sid_type: Option<ServiceSidType>,
+
+    /// The actions taken when the service fails.
+    pub failure_actions: Option<ServiceFailureActions>,
+
+    /// The events that start or stop the service. An empty list removes all triggers.
+    pub triggers: Option<Vec<ServiceTrigger>>,
+}
+
+impl ServiceManifest {
+    /// Create a manifest that only describes the basic configuration.
+    pub fn new(info: ServiceInfo) -> Self {
+        ServiceManifest {
+            info,
+            description: None,
+            delayed_auto_start: None,
+            sid_type: None,
+            failure_actions: None,
+            triggers: None,
+        }
+    }
+}
+
+/// A difference between a [`ServiceInfo`] and the [`ServiceConfig`] stored by the system.
+///
+/// Returned by [`ServiceInfo::differences`] and [`Service::verify_matches`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConfigDifference {
+    /// The service type differs.
+    ServiceType {
+        expected: ServiceType,
+        actual: ServiceType,
+    },
+    /// The start type differs.
+    StartType {
+        expected: ServiceStartType,
+        actual: ServiceStartType,
+    },
+    /// The error control differs.
+    ErrorControl {
+        expected: ServiceErrorControl,
+        actual: ServiceErrorControl,
+    },
+    /// The command line built from the executable path and launch arguments differs.
+    LaunchCommand {
+        expected: OsString,
+        actual: OsString,
+    },
+    /// The dependencies differ.
+    Dependencies {
+        expected: Vec<ServiceDependency>,
+        actual: Vec<ServiceDependency>,
+    },
+    /// The account the service runs as differs.
+    AccountName {
+        expected: OsString,
+        actual: Option<OsString>,
+    },
+    /// The display name differs.
+    DisplayName {
+        expected: OsString,
+        actual: OsString,
+    },
+}
+
+/// Enum describing the accounts that a service can run as.
+///
+/// Use [`ServiceAccount::account_name`] and [`ServiceAccount::account_password`] to fill in
+/// [`ServiceInfo::account_name`] and [`ServiceInfo::account_password`].
+///
+/// # Group managed service accounts
+///
+/// A group managed service account (gMSA) is a domain account whose password is managed by
+/// Active Directory. Services run as a gMSA by passing the account name with a trailing `$`, for
+/// example `DOMAIN\my_gmsa$`, and no password. [`ServiceAccount::GroupManaged`] takes care of
+/// both.
+///
+/// Before a service can run as a gMSA, the computer must be allowed to retrieve the managed
+/// password (`PrincipalsAllowedToRetrieveManagedPassword`), the account must be installed on the
+/// computer, for instance with `Install-ADServiceAccount`, and it needs the "Log on as a service"
+/// right. Service principal names required for Kerberos authentication must be registered on the
+/// gMSA itself rather than on the computer account.
+///
+/// Please refer to MSDN for more info:\
+/// <https://docs.microsoft.com/en-us/windows-server/security/group-managed-service-accounts/group-managed-service-accounts-overview>
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ServiceAccount {
+    /// The LocalSystem account.
+    LocalSystem,
+    /// The LocalService account.
+    LocalService,
+    /// The NetworkService account.
+    NetworkService,
+    /// A regular user account, in the form `DOMAIN\name` or `.\name` for local accounts.
+    User { name: OsString, password: OsString },
+    /// A group managed service account, in the form `DOMAIN\name`. The trailing `$` may be
+    /// omitted.
+    GroupManaged { name: OsString },
+}
+
+impl ServiceAccount {
+    /// Returns the account name to pass to the system, or `None` for LocalSystem.
+    pub fn account_name(&self) -> Option<OsString> {
+        match self {
+            ServiceAccount::LocalSystem => None,
+            ServiceAccount::LocalService => Some(OsString::from(r"NT AUTHORITY\LocalService")),
+            ServiceAccount::NetworkService => Some(OsString::from(r"NT AUTHORITY\NetworkService")),
+            ServiceAccount::User { name, .. } => Some(name.clone()),
+            ServiceAccount::GroupManaged { name } => {
+                let mut name = name.clone();
+                if name.encode_wide().last() != Some(b'$' as u16) {
+                    name.push("$");
+                }
+                Some(name)
+            }
+        }
+    }
+
+    /// Returns the account password to pass to the system.
+    ///
+    /// This is only set for [`ServiceAccount::User`], the passwords of the other accounts are
+    /// managed by the system.
+    pub fn account_password(&self) -> Option<OsString> {
+        match self {
+            ServiceAccount::User { password, .. } => Some(password.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// A security identifier (SID) that identifies an account.
+///
+/// The SID is stored in its binary form and displayed in the string form, for instance
+/// `S-1-5-18`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sid(Vec<u8>);
+
+impl Sid {
+    /// The relative identifier of the LocalSystem account.
+    const LOCAL_SYSTEM_RID: u32 = 18;
+    /// The relative identifier of the LocalService account.
+    const LOCAL_SERVICE_RID: u32 = 19;
+    /// The relative identifier of the NetworkService account.
+    const NETWORK_SERVICE_RID: u32 = 20;
+
+    /// Create the SID of an account in the `NT AUTHORITY` domain.
+    fn nt_authority(rid: u32) -> Sid {
+        let mut bytes = vec![1, 1, 0, 0, 0, 0, 0, 5];
+        bytes.extend_from_slice(&rid.to_le_bytes());
+        Sid(bytes)
+    }
+
+    /// Returns the binary form of the SID.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Sid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The binary form is the revision, the number of subauthorities, the 48-bit big-endian
+        // identifier authority and the 32-bit little-endian subauthorities.
+        let authority = self.0[2..8]
+            .iter()
+            .fold(0u64, |authority, byte| (authority << 8) | u64::from(*byte));
+        write!(f, "S-{}-{}", self.0[0], authority)?;
+        for subauthority in self.0[8..].chunks_exact(4) {
+            let subauthority = u32::from_le_bytes([
+                subauthority[0],
+                subauthority[1],
+                subauthority[2],
+                subauthority[3],
+            ]);
+            write!(f, "-{}", subauthority)?;
+        }
+        Ok(())
+    }
+}
+
+/// Look up the SID of the account with the given name on the local computer.
+///
+/// Returns `None` if the name does not map to an account.
+fn lookup_account_sid(account_name: &OsStr) -> crate::Result<Option<Sid>> {
+    let account_name = WideCString::from_os_str(account_name)
+        .map_err(|_| Error::ArgumentHasNulByte("account name"))?;
+
+    let mut sid_size: u32 = 0;
+    let mut domain_len: u32 = 0;
+    let mut sid_name_use: Security::SID_NAME_USE = 0;
+    let mut sid = Vec::new();
+    let mut domain: Vec<u16> = Vec::new();
+    loop {
+        let success = unsafe {
+            Security::LookupAccountNameW(
+                ptr::null(),
+                account_name.as_ptr(),
+                sid.as_mut_ptr() as Security::PSID,
+                &mut sid_size,
+                domain.as_mut_ptr(),
+                &mut domain_len,
+                &mut sid_name_use,
+            )
+        };
+        if success != 0 {
+            sid.truncate(sid_size as usize);
+            return Ok(Some(Sid(sid)));
+        }
+
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(code) if code == ERROR_NONE_MAPPED as i32 => return Ok(None),
+            // The sizes are updated with the required sizes, try again.
+            Some(code) if code == ERROR_INSUFFICIENT_BUFFER as i32 && sid.is_empty() => {
+                sid.resize(sid_size as usize, 0);
+                domain.resize(domain_len as usize, 0);
+            }
+            _ => return Err(Error::winapi(error)),
+        }
+    }
+}
+
 /// Same as `ServiceInfo` but with fields that are compatible with the Windows API.
 pub(crate) struct RawServiceInfo {
     /// Service name
@@ -441,12 +1063,20 @@ impl RawServiceInfo {
         let account_password = to_wide(service_info.account_password.as_ref())
             .map_err(|_| Error::ArgumentHasNulByte("account password"))?;
 
+        // per-user services are always user mode processes started in the user's session
+        if service_info.service_type.is_user_service()
+            && service_info.service_type.intersects(
+                ServiceType::KERNEL_DRIVER
+                    | ServiceType::FILE_SYSTEM_DRIVER
+                    | ServiceType::INTERACTIVE_PROCESS,
+            )
+        {
+            return Err(Error::InvalidUserServiceType);
+        }
+
         // escape executable path and arguments and combine them into a single command
         let mut launch_command_buffer = WideString::new();
-        if service_info
-            .service_type
-            .intersects(ServiceType::KERNEL_DRIVER | ServiceType::FILE_SYSTEM_DRIVER)
-        {
+        if service_info.service_type.is_driver() {
             // drivers do not support launch arguments
             if !service_info.launch_arguments.is_empty() {
                 return Err(Error::LaunchArgumentsNotSupported);
@@ -495,6 +1125,50 @@ impl RawServiceInfo {
     }
 }
 
+/// A service name that is converted to the wide string form used by the system only once.
+///
+/// Use it with [`ServiceManager::open_service_with_name`] to avoid converting the same name on
+/// every call.
+///
+/// [`ServiceManager::open_service_with_name`]: crate::service_manager::ServiceManager::open_service_with_name
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServiceName {
+    name: OsString,
+    wide_name: WideCString,
+}
+
+impl ServiceName {
+    /// Create a new service name.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ArgumentHasNulByte`] if the name contains a nul byte.
+    pub fn new(name: impl AsRef<OsStr>) -> crate::Result<Self> {
+        let name = name.as_ref();
+        let wide_name = WideCString::from_os_str(name)
+            .map_err(|_| Error::ArgumentHasNulByte("service name"))?;
+        Ok(ServiceName {
+            name: name.to_os_string(),
+            wide_name,
+        })
+    }
+
+    /// Returns the service name.
+    pub fn as_os_str(&self) -> &OsStr {
+        &self.name
+    }
+
+    pub(crate) fn as_wide_c_str(&self) -> &WideCStr {
+        &self.wide_name
+    }
+}
+
+impl AsRef<OsStr> for ServiceName {
+    fn as_ref(&self) -> &OsStr {
+        &self.name
+    }
+}
+
 /// A struct that describes the service.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ServiceConfig {
@@ -537,8 +1211,8 @@ impl ServiceConfig {
     /// # Errors
     ///
     /// Returns an error if `dwStartType` does not successfully convert into a
-    /// [`ServiceStartType`], or `dwErrorControl` does not successfully convert
-    /// into a [`ServiceErrorControl`].
+    /// [`ServiceStartType`]. Unknown `dwErrorControl` values are returned as
+    /// [`ServiceErrorControl::Unknown`].
     ///
     /// # Safety
     ///
@@ -571,8 +1245,7 @@ impl ServiceConfig {
             service_type: ServiceType::from_bits_truncate(raw.dwServiceType),
             start_type: ServiceStartType::from_raw(raw.dwStartType)
                 .map_err(|e| Error::ParseValue("service start type", e))?,
-            error_control: ServiceErrorControl::from_raw(raw.dwErrorControl)
-                .map_err(|e| Error::ParseValue("service error control", e))?,
+            error_control: ServiceErrorControl::from(raw.dwErrorControl),
             executable_path: PathBuf::from(
                 WideCStr::from_ptr_str(raw.lpBinaryPathName).to_os_string(),
             ),
@@ -583,61 +1256,376 @@ impl ServiceConfig {
             display_name: WideCStr::from_ptr_str(raw.lpDisplayName).to_os_string(),
         })
     }
-}
-
-/// Enum describing the event type of HardwareProfileChange
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(u32)]
-pub enum HardwareProfileChangeParam {
-    ConfigChanged = WindowsAndMessaging::DBT_CONFIGCHANGED,
-    QueryChangeConfig = WindowsAndMessaging::DBT_QUERYCHANGECONFIG,
-    ConfigChangeCanceled = WindowsAndMessaging::DBT_CONFIGCHANGECANCELED,
-}
 
-impl HardwareProfileChangeParam {
-    pub fn to_raw(&self) -> u32 {
-        *self as u32
+    /// Compare two configs, ignoring the values that are assigned by the system.
+    ///
+    /// Unlike `==`, this ignores [`ServiceConfig::tag_id`], which the service control manager
+    /// assigns and which is not part of what was configured. Use it to check whether an installed
+    /// service already has the desired configuration.
+    pub fn semantically_eq(&self, other: &ServiceConfig) -> bool {
+        let ServiceConfig {
+            service_type,
+            start_type,
+            error_control,
+            executable_path,
+            load_order_group,
+            tag_id: _,
+            dependencies,
+            account_name,
+            display_name,
+        } = self;
+        *service_type == other.service_type
+            && *start_type == other.start_type
+            && *error_control == other.error_control
+            && *executable_path == other.executable_path
+            && *load_order_group == other.load_order_group
+            && *dependencies == other.dependencies
+            && *account_name == other.account_name
+            && *display_name == other.display_name
     }
 
-    pub fn from_raw(raw: u32) -> Result<Self, ParseRawError> {
-        match raw {
-            x if x == HardwareProfileChangeParam::ConfigChanged.to_raw() => {
-                Ok(HardwareProfileChangeParam::ConfigChanged)
+    /// Resolve [`ServiceConfig::account_name`] to the SID of the account.
+    ///
+    /// The well-known accounts `LocalSystem`, `NT AUTHORITY\LocalService` and
+    /// `NT AUTHORITY\NetworkService` are mapped to their SIDs directly, as is a missing account
+    /// name, which means that the service runs as LocalSystem. Other names are looked up on the
+    /// local computer, so the config of a service on a remote computer may resolve differently
+    /// or not at all.
+    ///
+    /// Returns `None` for drivers, whose account name is the name of the driver object, and for
+    /// names that do not map to an account.
+    pub fn account_sid(&self) -> crate::Result<Option<Sid>> {
+        if self.service_type.is_driver() {
+            return Ok(None);
+        }
+        let account_name = match &self.account_name {
+            Some(account_name) => account_name,
+            None => return Ok(Some(Sid::nt_authority(Sid::LOCAL_SYSTEM_RID))),
+        };
+
+        let well_known = [
+            ("LocalSystem", Sid::LOCAL_SYSTEM_RID),
+            (r".\LocalSystem", Sid::LOCAL_SYSTEM_RID),
+            (r"NT AUTHORITY\SYSTEM", Sid::LOCAL_SYSTEM_RID),
+            (r"NT AUTHORITY\LocalService", Sid::LOCAL_SERVICE_RID),
+            (r"NT AUTHORITY\NetworkService", Sid::NETWORK_SERVICE_RID),
+        ];
+        for (name, rid) in well_known {
+            if eq_ignore_ascii_case_wide(account_name, OsStr::new(name)) {
+                return Ok(Some(Sid::nt_authority(rid)));
             }
-            x if x == HardwareProfileChangeParam::QueryChangeConfig.to_raw() => {
-                Ok(HardwareProfileChangeParam::QueryChangeConfig)
+        }
+        lookup_account_sid(account_name)
+    }
+
+    /// Returns `true` if the executable path contains whitespace but is not quoted.
+    ///
+    /// Windows resolves such a command line by trying each whitespace separated prefix in turn,
+    /// so `C:\Program Files\My App\service.exe` would first launch `C:\Program.exe` if it
+    /// exists. This is a well-known privilege escalation vector.
+    ///
+    /// If the executable does not end in `.exe`, it is not known where it ends. Such a command
+    /// line is reported if a path separator follows the first whitespace, as in
+    /// `C:\Program Files\My App\service --flag`.
+    ///
+    /// Driver services are never reported since their image path is not resolved this way.
+    pub fn has_unquoted_path_vulnerability(&self) -> bool {
+        if self.service_type.is_driver() {
+            return false;
+        }
+        match split_command_line(self.executable_path.as_os_str()) {
+            CommandLineExecutable::Path { path, quoted } => {
+                !quoted && path.encode_wide().any(is_wide_whitespace)
             }
-            x if x == HardwareProfileChangeParam::ConfigChangeCanceled.to_raw() => {
-                Ok(HardwareProfileChangeParam::ConfigChangeCanceled)
+            CommandLineExecutable::Ambiguous(prefixes) => {
+                let command_line: Vec<u16> = match prefixes.last() {
+                    Some(command_line) => command_line.encode_wide().collect(),
+                    None => return false,
+                };
+                command_line
+                    .iter()
+                    .position(|c| is_wide_whitespace(*c))
+                    .map_or(false, |whitespace| {
+                        command_line[whitespace..]
+                            .iter()
+                            .any(|c| *c == b'\\' as u16 || *c == b'/' as u16)
+                    })
             }
-            _ => Err(ParseRawError::InvalidInteger(raw)),
         }
     }
-}
-
-/// Enum indicates the current power source as
-/// the Data member of GUID_ACDC_POWER_SOURCE notification
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(i32)]
-pub enum PowerSource {
-    Ac = Power::PoAc,
-    Dc = Power::PoDc,
-    Hot = Power::PoHot,
-}
 
-impl PowerSource {
-    pub fn to_raw(&self) -> i32 {
-        *self as i32
+    /// Returns `true` if the service executable exists on disk.
+    ///
+    /// The executable is extracted from [`ServiceConfig::executable_path`], which may contain
+    /// launch arguments, the same way the system does it. Driver image paths relative to the
+    /// Windows directory, such as `System32\drivers\example.sys` or
+    /// `\SystemRoot\System32\drivers\example.sys`, are resolved against the `SystemRoot`
+    /// environment variable.
+    ///
+    /// If an unquoted executable path does not end in `.exe`, each whitespace separated prefix is
+    /// tried in turn, with `.exe` appended if it has no extension, as the system does.
+    ///
+    /// Returns `false` if the existence of the file cannot be determined, for instance due to
+    /// insufficient permissions.
+    ///
+    /// In a 32-bit process on 64-bit Windows, executables in `System32` are looked up in the
+    /// native `System32` directory, even when called from a 32-bit process on 64-bit Windows.
+    pub fn executable_exists(&self) -> bool {
+        self.executable_file_paths()
+            .into_iter()
+            .any(|path| native_system32_path(path).exists())
     }
 
-    pub fn from_raw(raw: i32) -> Result<PowerSource, ParseRawError> {
-        match raw {
-            x if x == PowerSource::Ac.to_raw() => Ok(PowerSource::Ac),
-            x if x == PowerSource::Dc.to_raw() => Ok(PowerSource::Dc),
-            x if x == PowerSource::Hot.to_raw() => Ok(PowerSource::Hot),
-            _ => Err(ParseRawError::InvalidIntegerSigned(raw)),
+    /// Private helper that resolves the paths at which the system looks for the service
+    /// executable, in order.
+    fn executable_file_paths(&self) -> Vec<PathBuf> {
+        if !self.service_type.is_driver() {
+            return match split_command_line(self.executable_path.as_os_str()) {
+                CommandLineExecutable::Path { path, .. } => vec![PathBuf::from(path)],
+                CommandLineExecutable::Ambiguous(prefixes) => prefixes
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .flat_map(|path| {
+                        let with_exe = match path.extension() {
+                            Some(_) => None,
+                            None => Some(path.with_extension("exe")),
+                        };
+                        std::iter::once(path).chain(with_exe)
+                    })
+                    .collect(),
+            };
         }
-    }
+
+        let wide: Vec<u16> = self.executable_path.as_os_str().encode_wide().collect();
+        let wide = strip_prefix_ignore_ascii_case(&wide, r"\??\").unwrap_or(&wide);
+        let system_root = || PathBuf::from(std::env::var_os("SystemRoot").unwrap_or_default());
+        if let Some(relative) = strip_prefix_ignore_ascii_case(wide, r"\SystemRoot\") {
+            return vec![system_root().join(OsString::from_wide(relative))];
+        }
+        let path = PathBuf::from(OsString::from_wide(wide));
+        if path.has_root() {
+            vec![path]
+        } else {
+            vec![system_root().join(path)]
+        }
+    }
+}
+
+/// Returns a path that refers to the native `System32` directory from the current process.
+///
+/// The service control manager is a native process, so `%SystemRoot%\System32` in a service
+/// executable path always refers to the native `System32` directory. A 32-bit process on 64-bit
+/// Windows (WOW64) however has its accesses to `System32` redirected to `SysWOW64`, so a 32-bit
+/// installer that checks for, copies or inspects a service binary under `System32` ends up
+/// looking at the wrong file. This function rewrites such paths to go through the `Sysnative`
+/// alias, which bypasses the redirection.
+///
+/// Use the returned path only for accessing files from the current process. Do not pass it to
+/// the service control manager, for instance in [`ServiceInfo::executable_path`], since
+/// `Sysnative` does not exist for native processes. See
+/// [`ServiceInfo::with_native_system32_path`] for the reverse rewrite.
+///
+/// The path is returned unchanged if the current process does not run under WOW64 or the path
+/// is not within `%SystemRoot%\System32`.
+pub(crate) fn native_system32_path(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    if !is_wow64_process() {
+        return path.to_path_buf();
+    }
+    std::env::var_os("SystemRoot")
+        .and_then(|system_root| {
+            replace_system_directory(path, &system_root, "System32", "Sysnative")
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Private helper that rewrites `<system_root>\<from>\...` to `<system_root>\<to>\...`.
+fn replace_system_directory(
+    path: &Path,
+    system_root: &OsStr,
+    from: &str,
+    to: &str,
+) -> Option<PathBuf> {
+    let wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+    let root_len = system_root.encode_wide().count();
+    if wide.len() < root_len
+        || !eq_ignore_ascii_case_wide(&OsString::from_wide(&wide[..root_len]), system_root)
+    {
+        return None;
+    }
+    let rest = strip_prefix_ignore_ascii_case(&wide[root_len..], &format!("\\{}", from))?;
+    if !(rest.is_empty() || rest[0] == b'\\' as u16) {
+        return None;
+    }
+    let mut replaced = Path::new(system_root).join(to).into_os_string();
+    replaced.push(OsString::from_wide(rest));
+    Some(PathBuf::from(replaced))
+}
+
+/// Returns `true` if the current process is a 32-bit process running on 64-bit Windows.
+fn is_wow64_process() -> bool {
+    let mut is_wow64 = 0;
+    let success =
+        unsafe { Threading::IsWow64Process(Threading::GetCurrentProcess(), &mut is_wow64) };
+    success != 0 && is_wow64 != 0
+}
+
+/// The executable part of a raw service command line, see [`split_command_line`].
+#[derive(Debug, PartialEq, Eq)]
+enum CommandLineExecutable {
+    /// The executable is quoted or ends in `.exe`.
+    Path { path: OsString, quoted: bool },
+    /// The executable is unquoted and does not end in `.exe`, so it may end at any whitespace.
+    /// Holds the whitespace separated prefixes of the command line, in the order the system
+    /// tries them, ending with the whole command line.
+    Ambiguous(Vec<OsString>),
+}
+
+/// Splits the executable out of a raw service command line.
+///
+/// Unquoted command lines are split after the first `.exe` that is followed by whitespace or the
+/// end of the string, which mirrors how the service control manager resolves them. Failing that,
+/// the executable is ambiguous.
+fn split_command_line(command_line: &OsStr) -> CommandLineExecutable {
+    const QUOTE: u16 = b'"' as u16;
+
+    let wide: Vec<u16> = command_line.encode_wide().collect();
+    let start = wide
+        .iter()
+        .position(|c| !is_wide_whitespace(*c))
+        .unwrap_or(wide.len());
+    let wide = &wide[start..];
+
+    if wide.first() == Some(&QUOTE) {
+        let rest = &wide[1..];
+        let end = rest.iter().position(|c| *c == QUOTE).unwrap_or(rest.len());
+        return CommandLineExecutable::Path {
+            path: OsString::from_wide(&rest[..end]),
+            quoted: true,
+        };
+    }
+
+    let extension: Vec<u16> = OsStr::new(".exe").encode_wide().collect();
+    let exe_end = (0..wide.len())
+        .filter(|i| wide.len() - i >= extension.len())
+        .find(|i| {
+            let end = i + extension.len();
+            let is_extension = wide[*i..end]
+                .iter()
+                .zip(&extension)
+                .all(|(c, e)| ascii_lowercase_wide(*c) == *e);
+            is_extension && wide.get(end).map_or(true, |c| is_wide_whitespace(*c))
+        })
+        .map(|i| i + extension.len());
+    match exe_end {
+        Some(end) => CommandLineExecutable::Path {
+            path: OsString::from_wide(&wide[..end]),
+            quoted: false,
+        },
+        None => {
+            let end = wide
+                .iter()
+                .rposition(|c| !is_wide_whitespace(*c))
+                .map_or(0, |i| i + 1);
+            let wide = &wide[..end];
+            let prefixes = (0..=wide.len())
+                .filter(|i| {
+                    *i == wide.len()
+                        || (is_wide_whitespace(wide[*i])
+                            && *i > 0
+                            && !is_wide_whitespace(wide[i - 1]))
+                })
+                .map(|i| OsString::from_wide(&wide[..i]))
+                .collect();
+            CommandLineExecutable::Ambiguous(prefixes)
+        }
+    }
+}
+
+fn is_wide_whitespace(c: u16) -> bool {
+    c == b' ' as u16 || c == b'\t' as u16
+}
+
+fn strip_prefix_ignore_ascii_case<'a>(s: &'a [u16], prefix: &str) -> Option<&'a [u16]> {
+    let prefix: Vec<u16> = OsStr::new(prefix).encode_wide().collect();
+    if s.len() >= prefix.len()
+        && s.iter()
+            .zip(&prefix)
+            .all(|(c, p)| ascii_lowercase_wide(*c) == ascii_lowercase_wide(*p))
+    {
+        Some(&s[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+fn eq_ignore_ascii_case_wide(a: &OsStr, b: &OsStr) -> bool {
+    a.encode_wide()
+        .map(ascii_lowercase_wide)
+        .eq(b.encode_wide().map(ascii_lowercase_wide))
+}
+
+fn ascii_lowercase_wide(c: u16) -> u16 {
+    if (b'A' as u16..=b'Z' as u16).contains(&c) {
+        c + (b'a' - b'A') as u16
+    } else {
+        c
+    }
+}
+
+/// Enum describing the event type of HardwareProfileChange
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum HardwareProfileChangeParam {
+    ConfigChanged = WindowsAndMessaging::DBT_CONFIGCHANGED,
+    QueryChangeConfig = WindowsAndMessaging::DBT_QUERYCHANGECONFIG,
+    ConfigChangeCanceled = WindowsAndMessaging::DBT_CONFIGCHANGECANCELED,
+}
+
+impl HardwareProfileChangeParam {
+    pub fn to_raw(&self) -> u32 {
+        *self as u32
+    }
+
+    pub fn from_raw(raw: u32) -> Result<Self, ParseRawError> {
+        match raw {
+            x if x == HardwareProfileChangeParam::ConfigChanged.to_raw() => {
+                Ok(HardwareProfileChangeParam::ConfigChanged)
+            }
+            x if x == HardwareProfileChangeParam::QueryChangeConfig.to_raw() => {
+                Ok(HardwareProfileChangeParam::QueryChangeConfig)
+            }
+            x if x == HardwareProfileChangeParam::ConfigChangeCanceled.to_raw() => {
+                Ok(HardwareProfileChangeParam::ConfigChangeCanceled)
+            }
+            _ => Err(ParseRawError::InvalidInteger(raw)),
+        }
+    }
+}
+
+/// Enum indicates the current power source as
+/// the Data member of GUID_ACDC_POWER_SOURCE notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum PowerSource {
+    Ac = Power::PoAc,
+    Dc = Power::PoDc,
+    Hot = Power::PoHot,
+}
+
+impl PowerSource {
+    pub fn to_raw(&self) -> i32 {
+        *self as i32
+    }
+
+    pub fn from_raw(raw: i32) -> Result<PowerSource, ParseRawError> {
+        match raw {
+            x if x == PowerSource::Ac.to_raw() => Ok(PowerSource::Ac),
+            x if x == PowerSource::Dc.to_raw() => Ok(PowerSource::Dc),
+            x if x == PowerSource::Hot.to_raw() => Ok(PowerSource::Hot),
+            _ => Err(ParseRawError::InvalidIntegerSigned(raw)),
+        }
+    }
 }
 
 /// Enum indicates the current monitor's display state as
@@ -903,6 +1891,17 @@ impl PowerBroadcastSetting {
             x => Err(ParseRawError::InvalidGuid(string_from_guid(x))),
         }
     }
+
+    /// Returns the remaining battery capacity in percent, clamped to the range 0 to 100, if
+    /// this is a [`PowerBroadcastSetting::BatteryPercentageRemaining`] notification.
+    pub fn battery_percent(&self) -> Option<u8> {
+        match *self {
+            PowerBroadcastSetting::BatteryPercentageRemaining(percentage) => {
+                Some(percentage.min(100) as u8)
+            }
+            _ => None,
+        }
+    }
 }
 
 /// Enum describing the PowerEvent event
@@ -1049,10 +2048,13 @@ impl SessionChangeParam {
         event_type: u32,
         event_data: *mut c_void,
     ) -> Result<Self, ParseRawError> {
+        // Validate the reason before reading the notification, so that unknown events are
+        // rejected without touching `event_data`.
+        let reason = SessionChangeReason::from_raw(event_type)?;
         let notification = *(event_data as *const RemoteDesktop::WTSSESSION_NOTIFICATION);
 
         Ok(SessionChangeParam {
-            reason: SessionChangeReason::from_raw(event_type)?,
+            reason,
             notification: SessionNotification::from_raw(notification),
         })
     }
@@ -1110,11 +2112,25 @@ pub enum ServiceControl {
     TimeChange,
     TriggerEvent,
     UserEvent(UserEventCode),
+    /// A control in the range reserved for the system, below 128, that this crate does not
+    /// recognize. Controls introduced by newer versions of Windows are delivered this way, so
+    /// that services can at least observe them. The event type and data are not parsed.
+    Unknown(u32),
 }
 
 impl ServiceControl {
     /// Convert to ServiceControl from parameters received by `service_control_handler`
     ///
+    /// Controls without parameters, such as [`ServiceControl::Stop`] and
+    /// [`ServiceControl::Interrogate`], are resolved from `raw` alone without reading
+    /// `event_type` or `event_data`, and parsing never allocates for them. `event_data` is only
+    /// read for [`ServiceControl::SessionChange`] and for power setting changes delivered with
+    /// [`ServiceControl::PowerEvent`].
+    ///
+    /// Unrecognized controls below 128 are returned as [`ServiceControl::Unknown`]. An error is
+    /// only returned for controls above 255, or when the event type of a recognized control is
+    /// not valid.
+    ///
     /// # Safety
     ///
     /// Invalid `event_data` pointer may cause undefined behavior in some circumstances.
@@ -1150,6 +2166,7 @@ impl ServiceControl {
             }
             Services::SERVICE_CONTROL_TIMECHANGE => Ok(ServiceControl::TimeChange),
             Services::SERVICE_CONTROL_TRIGGEREVENT => Ok(ServiceControl::TriggerEvent),
+            0..=127 => Ok(ServiceControl::Unknown(raw)),
             _ => UserEventCode::from_raw(raw).map(ServiceControl::UserEvent),
         }
     }
@@ -1175,8 +2192,22 @@ impl ServiceControl {
             ServiceControl::TimeChange => Services::SERVICE_CONTROL_TIMECHANGE,
             ServiceControl::TriggerEvent => Services::SERVICE_CONTROL_TRIGGEREVENT,
             ServiceControl::UserEvent(event) => event.to_raw(),
+            ServiceControl::Unknown(raw) => *raw,
         }
     }
+
+    /// Returns the flag a service must accept to receive this control, or `None` for controls
+    /// that are always delivered, such as [`ServiceControl::Interrogate`] and user-defined
+    /// controls.
+    ///
+    /// The mapping is defined by [`SERVICE_CONTROLS_BY_ACCEPT`].
+    pub fn required_accept(&self) -> Option<ServiceControlAccept> {
+        let raw = self.raw_service_control_type();
+        SERVICE_CONTROLS_BY_ACCEPT
+            .iter()
+            .find(|(_, controls)| controls.contains(&raw))
+            .map(|(accept, _)| *accept)
+    }
 }
 
 /// Service state returned as a part of [`ServiceStatus`].
@@ -1209,6 +2240,43 @@ impl ServiceState {
     fn to_raw(self) -> u32 {
         self as u32
     }
+
+    /// Returns `true` if a service may report `next` after having reported `self`.
+    ///
+    /// Reporting the same state again, for instance to advance the checkpoint of a pending
+    /// state, is always allowed, as is reporting [`ServiceState::Stopped`] to signal a failure.
+    /// Otherwise the legal transitions are:
+    ///
+    /// - `Stopped` to `StartPending`
+    /// - `StartPending` to `Running` or `StopPending`
+    /// - `Running` to `PausePending` or `StopPending`
+    /// - `PausePending` to `Paused`, `Running` or `StopPending`
+    /// - `Paused` to `ContinuePending` or `StopPending`
+    /// - `ContinuePending` to `Running`, `Paused` or `StopPending`
+    ///
+    /// A service launched by the system starts out in `StartPending`.
+    pub fn can_transition_to(&self, next: ServiceState) -> bool {
+        use ServiceState::*;
+        if *self == next || next == Stopped {
+            return true;
+        }
+        matches!(
+            (*self, next),
+            (Stopped, StartPending)
+                | (StartPending, Running)
+                | (StartPending, StopPending)
+                | (Running, PausePending)
+                | (Running, StopPending)
+                | (PausePending, Paused)
+                | (PausePending, Running)
+                | (PausePending, StopPending)
+                | (Paused, ContinuePending)
+                | (Paused, StopPending)
+                | (ContinuePending, Running)
+                | (ContinuePending, Paused)
+                | (ContinuePending, StopPending)
+        )
+    }
 }
 
 /// Service exit code abstraction.
@@ -1227,7 +2295,13 @@ impl ServiceState {
 /// [`dwServiceSpecificExitCode`]: Services::SERVICE_STATUS::dwServiceSpecificExitCode
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ServiceExitCode {
+    /// A win32 error code. `Win32(NO_ERROR)` means that no error occurred.
     Win32(u32),
+    /// A service specific error code.
+    ///
+    /// This is reported whenever `dwWin32ExitCode` is [`ERROR_SERVICE_SPECIFIC_ERROR`], even if
+    /// `dwServiceSpecificExitCode` is zero. `ServiceSpecific(0)` is therefore a failure with the
+    /// service specific code 0 and is never considered a success.
     ServiceSpecific(u32),
 }
 
@@ -1235,6 +2309,19 @@ impl ServiceExitCode {
     /// A `ServiceExitCode` indicating success, no errors.
     pub const NO_ERROR: Self = ServiceExitCode::Win32(NO_ERROR);
 
+    /// Returns `true` if the exit code indicates success, i.e. is [`ServiceExitCode::NO_ERROR`].
+    pub fn is_success(&self) -> bool {
+        *self == Self::NO_ERROR
+    }
+
+    fn from_raw(win32_exit_code: u32, service_specific_exit_code: u32) -> Self {
+        if win32_exit_code == ERROR_SERVICE_SPECIFIC_ERROR {
+            ServiceExitCode::ServiceSpecific(service_specific_exit_code)
+        } else {
+            ServiceExitCode::Win32(win32_exit_code)
+        }
+    }
+
     fn copy_to(&self, raw_service_status: &mut Services::SERVICE_STATUS) {
         match *self {
             ServiceExitCode::Win32(win32_error_code) => {
@@ -1257,21 +2344,19 @@ impl Default for ServiceExitCode {
 
 impl<'a> From<&'a Services::SERVICE_STATUS> for ServiceExitCode {
     fn from(service_status: &'a Services::SERVICE_STATUS) -> Self {
-        if service_status.dwWin32ExitCode == ERROR_SERVICE_SPECIFIC_ERROR {
-            ServiceExitCode::ServiceSpecific(service_status.dwServiceSpecificExitCode)
-        } else {
-            ServiceExitCode::Win32(service_status.dwWin32ExitCode)
-        }
+        Self::from_raw(
+            service_status.dwWin32ExitCode,
+            service_status.dwServiceSpecificExitCode,
+        )
     }
 }
 
 impl<'a> From<&'a Services::SERVICE_STATUS_PROCESS> for ServiceExitCode {
     fn from(service_status: &'a Services::SERVICE_STATUS_PROCESS) -> Self {
-        if service_status.dwWin32ExitCode == ERROR_SERVICE_SPECIFIC_ERROR {
-            ServiceExitCode::ServiceSpecific(service_status.dwServiceSpecificExitCode)
-        } else {
-            ServiceExitCode::Win32(service_status.dwWin32ExitCode)
-        }
+        Self::from_raw(
+            service_status.dwWin32ExitCode,
+            service_status.dwServiceSpecificExitCode,
+        )
     }
 }
 
@@ -1324,6 +2409,149 @@ bitflags::bitflags! {
     }
 }
 
+impl ServiceControlAccept {
+    /// The controls that per-user services do not receive.
+    ///
+    /// Per-user service instances live in the session of the user they were created for and are
+    /// stopped with [`ServiceControl::Stop`] when the user logs off, including when the system
+    /// shuts down, so they are not sent the shutdown notifications. Network binding and
+    /// hardware profile changes are only delivered to system services.
+    pub const UNSUPPORTED_BY_USER_SERVICES: Self = Self::NETBIND_CHANGE
+        .union(Self::HARDWARE_PROFILE_CHANGE)
+        .union(Self::SHUTDOWN)
+        .union(Self::PRESHUTDOWN);
+
+    /// Returns the controls that a per-user service can accept out of these controls.
+    ///
+    /// Use this when reusing the accepted controls of a system service for a per-user service,
+    /// so that the service does not claim to accept controls it never receives. Such a service
+    /// should do its cleanup when handling [`ServiceControl::Stop`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use windows_service::service::ServiceControlAccept;
+    ///
+    /// let controls = ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN;
+    /// assert_eq!(controls.for_user_service(), ServiceControlAccept::STOP);
+    /// ```
+    pub fn for_user_service(self) -> Self {
+        self - Self::UNSUPPORTED_BY_USER_SERVICES
+    }
+
+    /// Returns the controls that a service of the given type can receive.
+    ///
+    /// Drivers do not receive any controls, and per-user services do not receive
+    /// [`ServiceControlAccept::UNSUPPORTED_BY_USER_SERVICES`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use windows_service::service::{ServiceControlAccept, ServiceType};
+    ///
+    /// let supported = ServiceControlAccept::supported_by(ServiceType::USER_OWN_PROCESS);
+    /// assert!(supported.contains(ServiceControlAccept::SESSION_CHANGE));
+    /// assert!(!supported.contains(ServiceControlAccept::SHUTDOWN));
+    /// ```
+    pub fn supported_by(service_type: ServiceType) -> Self {
+        if service_type.is_driver() {
+            Self::empty()
+        } else if service_type.is_user_service() {
+            Self::all().for_user_service()
+        } else {
+            Self::all()
+        }
+    }
+}
+
+/// Table mapping each [`ServiceControlAccept`] flag to the raw control codes that the service
+/// receives when accepting it.
+///
+/// The control codes correspond to [`ServiceControl::raw_service_control_type`].
+/// [`ServiceControl::Interrogate`] and user-defined controls are always delivered and do not
+/// appear in the table.
+pub const SERVICE_CONTROLS_BY_ACCEPT: &[(ServiceControlAccept, &[u32])] = &[
+    (
+        ServiceControlAccept::NETBIND_CHANGE,
+        &[
+            Services::SERVICE_CONTROL_NETBINDADD,
+            Services::SERVICE_CONTROL_NETBINDDISABLE,
+            Services::SERVICE_CONTROL_NETBINDENABLE,
+            Services::SERVICE_CONTROL_NETBINDREMOVE,
+        ],
+    ),
+    (
+        ServiceControlAccept::PARAM_CHANGE,
+        &[Services::SERVICE_CONTROL_PARAMCHANGE],
+    ),
+    (
+        ServiceControlAccept::PAUSE_CONTINUE,
+        &[
+            Services::SERVICE_CONTROL_PAUSE,
+            Services::SERVICE_CONTROL_CONTINUE,
+        ],
+    ),
+    (
+        ServiceControlAccept::PRESHUTDOWN,
+        &[Services::SERVICE_CONTROL_PRESHUTDOWN],
+    ),
+    (
+        ServiceControlAccept::SHUTDOWN,
+        &[Services::SERVICE_CONTROL_SHUTDOWN],
+    ),
+    (
+        ServiceControlAccept::STOP,
+        &[Services::SERVICE_CONTROL_STOP],
+    ),
+    (
+        ServiceControlAccept::HARDWARE_PROFILE_CHANGE,
+        &[Services::SERVICE_CONTROL_HARDWAREPROFILECHANGE],
+    ),
+    (
+        ServiceControlAccept::POWER_EVENT,
+        &[Services::SERVICE_CONTROL_POWEREVENT],
+    ),
+    (
+        ServiceControlAccept::SESSION_CHANGE,
+        &[Services::SERVICE_CONTROL_SESSIONCHANGE],
+    ),
+    (
+        ServiceControlAccept::TIME_CHANGE,
+        &[Services::SERVICE_CONTROL_TIMECHANGE],
+    ),
+    (
+        ServiceControlAccept::TRIGGER_EVENT,
+        &[Services::SERVICE_CONTROL_TRIGGEREVENT],
+    ),
+];
+
+impl ServiceControlAccept {
+    /// Resolve the conflict between [`ServiceControlAccept::SHUTDOWN`] and
+    /// [`ServiceControlAccept::PRESHUTDOWN`] in favor of preshutdown.
+    ///
+    /// A service cannot accept both. Preshutdown notifications are delivered before shutdown
+    /// and the service can delay the shutdown while it cleans up, which is usually the better
+    /// choice for services that need more time. If both flags are set, `SHUTDOWN` is removed,
+    /// otherwise the flags are returned unchanged.
+    pub fn prefer_preshutdown(self) -> Self {
+        if self.contains(Self::SHUTDOWN | Self::PRESHUTDOWN) {
+            self - Self::SHUTDOWN
+        } else {
+            self
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags describing the process that hosts a service.
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Default)]
+    pub struct ServiceFlags: u32 {
+        /// The service runs in a system process that must always be running, such as
+        /// `services.exe`.
+        const RUNS_IN_SYSTEM_PROCESS = Services::SERVICE_RUNS_IN_SYSTEM_PROCESS;
+    }
+}
+
 /// Service status.
 ///
 /// This struct wraps the lower level [`SERVICE_STATUS`] providing a few convenience types to fill
@@ -1372,11 +2600,38 @@ pub struct ServiceStatus {
 }
 
 impl ServiceStatus {
+    /// Create a builder for a status to report for a service of the given type in the given
+    /// state.
+    ///
+    /// See [`ServiceStatusBuilder::build`] for the rules that the built status is checked
+    /// against.
+    pub fn builder(service_type: ServiceType, current_state: ServiceState) -> ServiceStatusBuilder {
+        ServiceStatusBuilder {
+            status: ServiceStatus {
+                service_type,
+                current_state,
+                controls_accepted: ServiceControlAccept::empty(),
+                exit_code: ServiceExitCode::NO_ERROR,
+                checkpoint: 0,
+                wait_hint: Duration::default(),
+                process_id: None,
+            },
+        }
+    }
+
+    /// Convert into the raw status reported to the system.
+    ///
+    /// No controls are reported for [`ServiceState::Stopped`], since the system ignores them in
+    /// that state and stale bits are confusing to tools that display them.
     pub(crate) fn to_raw(&self) -> Services::SERVICE_STATUS {
         let mut raw_status = unsafe { mem::zeroed::<Services::SERVICE_STATUS>() };
         raw_status.dwServiceType = self.service_type.bits();
         raw_status.dwCurrentState = self.current_state.to_raw();
-        raw_status.dwControlsAccepted = self.controls_accepted.bits();
+        raw_status.dwControlsAccepted = if self.current_state == ServiceState::Stopped {
+            0
+        } else {
+            self.controls_accepted.bits()
+        };
 
         self.exit_code.copy_to(&mut raw_status);
 
@@ -1388,6 +2643,68 @@ impl ServiceStatus {
         raw_status
     }
 
+    /// Compare two statuses, ignoring the values that identify a particular run of the service.
+    ///
+    /// Unlike `==`, this ignores [`ServiceStatus::process_id`], which changes every time the
+    /// service is started.
+    pub fn semantically_eq(&self, other: &ServiceStatus) -> bool {
+        let ServiceStatus {
+            service_type,
+            current_state,
+            controls_accepted,
+            exit_code,
+            checkpoint,
+            wait_hint,
+            process_id: _,
+        } = self;
+        *service_type == other.service_type
+            && *current_state == other.current_state
+            && *controls_accepted == other.controls_accepted
+            && *exit_code == other.exit_code
+            && *checkpoint == other.checkpoint
+            && *wait_hint == other.wait_hint
+    }
+
+    /// Checks that this status can be reported for a service of the given type.
+    ///
+    /// A status whose type does not match the installed service type is a common reason for a
+    /// service not receiving the control events it declares in
+    /// [`ServiceStatus::controls_accepted`]. Pass the type returned by [`Service::query_config`]
+    /// to catch such mistakes before calling
+    /// [`ServiceStatusHandle::set_service_status`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidServiceStatus`] if `service_type` is a driver type, since drivers
+    /// do not report their status, or if [`ServiceStatus::service_type`] differs from
+    /// `service_type`. [`ServiceType::INTERACTIVE_PROCESS`] and
+    /// [`ServiceType::USER_SERVICE_INSTANCE`] are ignored in the comparison.
+    ///
+    /// An error is also returned if the status accepts controls that a service of the given type
+    /// never receives, see [`ServiceControlAccept::supported_by`]. For instance, per-user
+    /// services do not receive [`ServiceControlAccept::UNSUPPORTED_BY_USER_SERVICES`].
+    ///
+    /// [`ServiceStatusHandle::set_service_status`]: crate::service_control_handler::ServiceStatusHandle::set_service_status
+    pub fn validate_against(&self, service_type: ServiceType) -> crate::Result<()> {
+        if service_type.is_driver() || self.service_type.is_driver() {
+            return Err(Error::InvalidServiceStatus(
+                "driver services do not report their status",
+            ));
+        }
+        let ignored_types = ServiceType::INTERACTIVE_PROCESS | ServiceType::USER_SERVICE_INSTANCE;
+        if self.service_type - ignored_types != service_type - ignored_types {
+            return Err(Error::InvalidServiceStatus(
+                "service type does not match the type of the service",
+            ));
+        }
+        if !ServiceControlAccept::supported_by(service_type).contains(self.controls_accepted) {
+            return Err(Error::InvalidServiceStatus(
+                "the service type does not receive some of the accepted controls",
+            ));
+        }
+        Ok(())
+    }
+
     /// Tries to parse a `SERVICE_STATUS` into a Rust [`ServiceStatus`].
     ///
     /// # Errors
@@ -1410,7 +2727,9 @@ impl ServiceStatus {
     /// # Errors
     ///
     /// Returns an error if the `dwCurrentState` field does not represent a valid [`ServiceState`].
-    fn from_raw_ex(raw: Services::SERVICE_STATUS_PROCESS) -> Result<Self, ParseRawError> {
+    pub(crate) fn from_raw_ex(
+        raw: Services::SERVICE_STATUS_PROCESS,
+    ) -> Result<Self, ParseRawError> {
         let current_state = ServiceState::from_raw(raw.dwCurrentState)?;
         let process_id = match current_state {
             ServiceState::Running => Some(raw.dwProcessId),
@@ -1426,11 +2745,203 @@ impl ServiceStatus {
             process_id,
         })
     }
+
+    /// The version of the byte format written by [`ServiceStatus::to_bytes`].
+    pub const BYTES_VERSION: u8 = 1;
+
+    /// The length of the byte format written by [`ServiceStatus::to_bytes`].
+    pub const BYTES_LEN: usize = 36;
+
+    /// Serialize the status into a stable byte format, for instance to send it to another
+    /// process without depending on the layout of `SERVICE_STATUS`.
+    ///
+    /// The format is [`ServiceStatus::BYTES_LEN`] bytes long and laid out as follows, with all
+    /// integers in little-endian byte order:
+    ///
+    /// | Offset | Size | Field                                                              |
+    /// |--------|------|--------------------------------------------------------------------|
+    /// | 0      | 1    | Version, [`ServiceStatus::BYTES_VERSION`]                          |
+    /// | 1      | 1    | Exit code kind, 0 for [`ServiceExitCode::Win32`] and 1 for [`ServiceExitCode::ServiceSpecific`] |
+    /// | 2      | 1    | 1 if [`ServiceStatus::process_id`] is set, otherwise 0             |
+    /// | 3      | 1    | Reserved, 0                                                        |
+    /// | 4      | 4    | [`ServiceStatus::service_type`]                                    |
+    /// | 8      | 4    | [`ServiceStatus::current_state`]                                   |
+    /// | 12     | 4    | [`ServiceStatus::controls_accepted`]                               |
+    /// | 16     | 4    | Exit code                                                          |
+    /// | 20     | 4    | [`ServiceStatus::checkpoint`]                                      |
+    /// | 24     | 8    | [`ServiceStatus::wait_hint`] in milliseconds, saturating           |
+    /// | 32     | 4    | [`ServiceStatus::process_id`], 0 if not set                        |
+    ///
+    /// The version is only changed if the meaning of the existing fields changes. New fields are
+    /// appended instead, so that older readers can still read the fields they know about.
+    pub fn to_bytes(&self) -> [u8; Self::BYTES_LEN] {
+        let (exit_code_kind, exit_code) = match self.exit_code {
+            ServiceExitCode::Win32(code) => (0, code),
+            ServiceExitCode::ServiceSpecific(code) => (1, code),
+        };
+        let wait_hint = u64::try_from(self.wait_hint.as_millis()).unwrap_or(u64::MAX);
+
+        let mut bytes = [0u8; Self::BYTES_LEN];
+        bytes[0] = Self::BYTES_VERSION;
+        bytes[1] = exit_code_kind;
+        bytes[2] = self.process_id.is_some() as u8;
+        bytes[4..8].copy_from_slice(&self.service_type.bits().to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.current_state.to_raw().to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.controls_accepted.bits().to_le_bytes());
+        bytes[16..20].copy_from_slice(&exit_code.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.checkpoint.to_le_bytes());
+        bytes[24..32].copy_from_slice(&wait_hint.to_le_bytes());
+        bytes[32..36].copy_from_slice(&self.process_id.unwrap_or(0).to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a status written by [`ServiceStatus::to_bytes`].
+    ///
+    /// Bytes following the fields of the current version are ignored, since they hold fields
+    /// appended by newer versions of the crate.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseRawError::InvalidLength`] if `bytes` is shorter than
+    /// [`ServiceStatus::BYTES_LEN`], or [`ParseRawError::InvalidInteger`] if the version, the exit
+    /// code kind or the service state is not valid.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseRawError> {
+        if bytes.len() < Self::BYTES_LEN {
+            return Err(ParseRawError::InvalidLength(bytes.len()));
+        }
+        if bytes[0] != Self::BYTES_VERSION {
+            return Err(ParseRawError::InvalidInteger(u32::from(bytes[0])));
+        }
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ])
+        };
+        let exit_code = match bytes[1] {
+            0 => ServiceExitCode::Win32(u32_at(16)),
+            1 => ServiceExitCode::ServiceSpecific(u32_at(16)),
+            kind => return Err(ParseRawError::InvalidInteger(u32::from(kind))),
+        };
+        let mut wait_hint = [0u8; 8];
+        wait_hint.copy_from_slice(&bytes[24..32]);
+
+        Ok(ServiceStatus {
+            service_type: ServiceType::from_bits_truncate(u32_at(4)),
+            current_state: ServiceState::from_raw(u32_at(8))?,
+            controls_accepted: ServiceControlAccept::from_bits_truncate(u32_at(12)),
+            exit_code,
+            checkpoint: u32_at(20),
+            wait_hint: Duration::from_millis(u64::from_le_bytes(wait_hint)),
+            process_id: if bytes[2] != 0 {
+                Some(u32_at(32))
+            } else {
+                None
+            },
+        })
+    }
+}
+
+/// A builder for a [`ServiceStatus`] that checks the `SERVICE_STATUS` rules when building.
+///
+/// Created with [`ServiceStatus::builder`]. The status accepts no controls, reports
+/// [`ServiceExitCode::NO_ERROR`] and has a zero checkpoint and wait hint unless set otherwise.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use windows_service::service::{ServiceControlAccept, ServiceState, ServiceStatus, ServiceType};
+///
+/// # fn main() -> windows_service::Result<()> {
+/// let starting = ServiceStatus::builder(ServiceType::OWN_PROCESS, ServiceState::StartPending)
+///     .checkpoint(1)
+///     .wait_hint(Duration::from_secs(10))
+///     .build()?;
+/// let running = ServiceStatus::builder(ServiceType::OWN_PROCESS, ServiceState::Running)
+///     .controls_accepted(ServiceControlAccept::STOP)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServiceStatusBuilder {
+    status: ServiceStatus,
+}
+
+impl ServiceStatusBuilder {
+    /// Set the controls that the service accepts.
+    pub fn controls_accepted(mut self, controls_accepted: ServiceControlAccept) -> Self {
+        self.status.controls_accepted = controls_accepted;
+        self
+    }
+
+    /// Set the exit code.
+    pub fn exit_code(mut self, exit_code: ServiceExitCode) -> Self {
+        self.status.exit_code = exit_code;
+        self
+    }
+
+    /// Set the checkpoint of a pending operation.
+    pub fn checkpoint(mut self, checkpoint: u32) -> Self {
+        self.status.checkpoint = checkpoint;
+        self
+    }
+
+    /// Set the estimated time of a pending operation.
+    pub fn wait_hint(mut self, wait_hint: Duration) -> Self {
+        self.status.wait_hint = wait_hint;
+        self
+    }
+
+    /// Build the status.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidServiceStatus`] if:
+    ///
+    /// * The state is [`ServiceState::Stopped`] and the status accepts any controls, since a
+    ///   stopped service cannot receive controls.
+    /// * The state is not a pending state and either the checkpoint or the wait hint is not
+    ///   zero, since they only describe the progress of pending operations.
+    /// * The wait hint does not fit as milliseconds in a `u32`.
+    ///
+    /// Note that a paused service keeps accepting [`ServiceControlAccept::PAUSE_CONTINUE`] so
+    /// that it can be continued.
+    ///
+    /// [`Error::InvalidServiceStatus`]: crate::Error::InvalidServiceStatus
+    pub fn build(self) -> crate::Result<ServiceStatus> {
+        let status = self.status;
+        let is_pending = matches!(
+            status.current_state,
+            ServiceState::StartPending
+                | ServiceState::StopPending
+                | ServiceState::PausePending
+                | ServiceState::ContinuePending
+        );
+        if status.current_state == ServiceState::Stopped && !status.controls_accepted.is_empty() {
+            return Err(Error::InvalidServiceStatus(
+                "a stopped service cannot accept controls",
+            ));
+        }
+        if !is_pending && (status.checkpoint != 0 || status.wait_hint != Duration::default()) {
+            return Err(Error::InvalidServiceStatus(
+                "checkpoint and wait hint must be zero outside of pending states",
+            ));
+        }
+        if u32::try_from(status.wait_hint.as_millis()).is_err() {
+            return Err(Error::InvalidServiceStatus("wait hint is too long"));
+        }
+        Ok(status)
+    }
 }
 
 /// This controls how the service SID is added to the service process token.
 /// <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-service_sid_info>
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u32)]
 pub enum ServiceSidType {
     None = 0,
@@ -1438,27 +2949,313 @@ pub enum ServiceSidType {
     Unrestricted = 1,
 }
 
-/// A struct that represents a system service.
-///
-/// The instances of the [`Service`] can be obtained via [`ServiceManager`].
+/// Enum describing the protection level that the service is launched with.
 ///
-/// [`ServiceManager`]: super::service_manager::ServiceManager
-pub struct Service {
-    service_handle: ScHandle,
+/// Please refer to MSDN for more info:\
+/// <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-service_launch_protected_info>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ServiceLaunchProtected {
+    /// The service is not launched protected.
+    None = Services::SERVICE_LAUNCH_PROTECTED_NONE,
+    /// The service is launched as a Windows protected process.
+    Windows = Services::SERVICE_LAUNCH_PROTECTED_WINDOWS,
+    /// The service is launched as a Windows protected process light.
+    WindowsLight = Services::SERVICE_LAUNCH_PROTECTED_WINDOWS_LIGHT,
+    /// The service is launched as an antimalware protected process light.
+    AntimalwareLight = Services::SERVICE_LAUNCH_PROTECTED_ANTIMALWARE_LIGHT,
 }
 
-impl Service {
-    pub(crate) fn new(service_handle: ScHandle) -> Self {
-        Service { service_handle }
+impl ServiceLaunchProtected {
+    pub fn to_raw(&self) -> u32 {
+        *self as u32
     }
 
-    /// Provides access to the underlying system service handle
-    pub fn raw_handle(&self) -> Services::SC_HANDLE {
-        self.service_handle.raw_handle()
+    pub fn from_raw(raw: u32) -> Result<Self, ParseRawError> {
+        match raw {
+            x if x == ServiceLaunchProtected::None.to_raw() => Ok(ServiceLaunchProtected::None),
+            x if x == ServiceLaunchProtected::Windows.to_raw() => {
+                Ok(ServiceLaunchProtected::Windows)
+            }
+            x if x == ServiceLaunchProtected::WindowsLight.to_raw() => {
+                Ok(ServiceLaunchProtected::WindowsLight)
+            }
+            x if x == ServiceLaunchProtected::AntimalwareLight.to_raw() => {
+                Ok(ServiceLaunchProtected::AntimalwareLight)
+            }
+            _ => Err(ParseRawError::InvalidInteger(raw)),
+        }
     }
+}
 
+/// Enum describing the action taken when a service trigger event occurs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum ServiceTriggerAction {
     /// Start the service.
-    ///
+    Start = Services::SERVICE_TRIGGER_ACTION_SERVICE_START,
+    /// Stop the service.
+    Stop = Services::SERVICE_TRIGGER_ACTION_SERVICE_STOP,
+}
+
+impl ServiceTriggerAction {
+    pub fn to_raw(&self) -> u32 {
+        *self as u32
+    }
+
+    pub fn from_raw(raw: u32) -> Result<Self, ParseRawError> {
+        match raw {
+            x if x == ServiceTriggerAction::Start.to_raw() => Ok(ServiceTriggerAction::Start),
+            x if x == ServiceTriggerAction::Stop.to_raw() => Ok(ServiceTriggerAction::Stop),
+            _ => Err(ParseRawError::InvalidInteger(raw)),
+        }
+    }
+}
+
+/// A trigger specific data item, which narrows down the events that fire a [`ServiceTrigger`].
+///
+/// Please refer to MSDN for more info:\
+/// <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-service_trigger_specific_data_item>
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceTriggerDataItem {
+    /// The raw data type, one of the `SERVICE_TRIGGER_DATA_TYPE_*` constants.
+    pub data_type: u32,
+
+    /// The raw data, in the format given by the data type.
+    pub data: Vec<u8>,
+}
+
+/// An event that starts or stops the service.
+///
+/// Please refer to MSDN for more info:\
+/// <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-service_trigger>
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceTrigger {
+    /// The raw trigger event type, one of the `SERVICE_TRIGGER_TYPE_*` constants.
+    pub trigger_type: u32,
+
+    /// The action to take when the trigger event occurs.
+    pub action: ServiceTriggerAction,
+
+    /// The trigger event subtype, a GUID in the same form as accepted by `GUID::from_u128`.
+    pub subtype: Option<u128>,
+
+    /// The trigger specific data items. Empty if the trigger fires on any event of its type.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub data_items: Vec<ServiceTriggerDataItem>,
+}
+
+impl ServiceTrigger {
+    /// Tries to parse a `SERVICE_TRIGGER` into a Rust [`ServiceTrigger`].
+    ///
+    /// # Safety
+    ///
+    /// `pTriggerSubtype` must be either null or point to a valid `GUID`.
+    /// `pDataItems` must be either null or an array with `cDataItems` number of
+    /// `SERVICE_TRIGGER_SPECIFIC_DATA_ITEM`s, each pointing to `cbData` bytes of data.
+    unsafe fn from_raw(raw: &Services::SERVICE_TRIGGER) -> crate::Result<ServiceTrigger> {
+        let data_items = if raw.pDataItems.is_null() {
+            Vec::new()
+        } else {
+            (0..raw.cDataItems as usize)
+                .map(|i| {
+                    let raw_item = ptr::read_unaligned(raw.pDataItems.add(i));
+                    let data = if raw_item.pData.is_null() {
+                        Vec::new()
+                    } else {
+                        slice::from_raw_parts(raw_item.pData, raw_item.cbData as usize).to_vec()
+                    };
+                    ServiceTriggerDataItem {
+                        data_type: raw_item.dwDataType,
+                        data,
+                    }
+                })
+                .collect()
+        };
+        Ok(ServiceTrigger {
+            trigger_type: raw.dwTriggerType,
+            action: ServiceTriggerAction::from_raw(raw.dwAction)
+                .map_err(|e| Error::ParseValue("service trigger action", e))?,
+            subtype: raw.pTriggerSubtype.as_ref().map(u128_from_guid),
+            data_items,
+        })
+    }
+}
+
+/// A snapshot of the complete configuration of a service.
+///
+/// The optional components are queried individually and are `None` if the running version of
+/// Windows does not support them or they are not set.
+///
+/// See [`Service::query_full_config`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FullServiceConfig {
+    /// The basic service configuration.
+    pub config: ServiceConfig,
+
+    /// The service description.
+    pub description: Option<OsString>,
+
+    /// Whether an auto-start service is started after the other auto-start services.
+    pub delayed_auto_start: Option<bool>,
+
+    /// How the service SID is added to the service process token.
+    pub sid_type: Option<ServiceSidType>,
+
+    /// The privileges that the service process is limited to. Empty if the service process
+    /// keeps all privileges of its account.
+    pub required_privileges: Option<Vec<OsString>>,
+
+    /// How long the system waits for the service to stop after sending it
+    /// [`ServiceControl::Preshutdown`].
+    pub preshutdown_timeout: Option<Duration>,
+
+    /// The actions taken when the service fails.
+    pub failure_actions: Option<ServiceFailureActions>,
+
+    /// Whether the failure actions are also run on non-crash failures.
+    pub failure_flag: Option<bool>,
+
+    /// The protection level that the service is launched with.
+    pub launch_protected: Option<ServiceLaunchProtected>,
+
+    /// The events that start or stop the service.
+    pub triggers: Option<Vec<ServiceTrigger>>,
+}
+
+impl FullServiceConfig {
+    /// Check the configuration for combinations of settings that contradict each other.
+    ///
+    /// Such configurations are accepted by the system but lead to unexpected start behavior.
+    /// Components that could not be queried are not checked.
+    pub fn warnings(&self) -> Vec<ConfigWarning> {
+        let mut warnings = Vec::new();
+        let has_triggers = self
+            .triggers
+            .as_ref()
+            .map_or(false, |triggers| !triggers.is_empty());
+        if self.delayed_auto_start == Some(true) && has_triggers {
+            warnings.push(ConfigWarning::DelayedAutoStartWithTriggers);
+        }
+        warnings
+    }
+}
+
+/// A contradictory combination of settings in a [`FullServiceConfig`].
+///
+/// Returned by [`FullServiceConfig::warnings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConfigWarning {
+    /// The service is configured both for delayed auto-start and with start triggers. Whether
+    /// the service starts at boot, after the delay or when a trigger fires then depends on the
+    /// version of Windows and the start type.
+    DelayedAutoStartWithTriggers,
+}
+
+/// A struct that represents a system service.
+///
+/// The instances of the [`Service`] can be obtained via [`ServiceManager`].
+///
+/// [`ServiceManager`]: super::service_manager::ServiceManager
+pub struct Service {
+    service_handle: ScHandle,
+    config_cache: Mutex<ConfigCache>,
+}
+
+/// Returns whether a service that went from `previous` to `current` state while being waited on
+/// to reach `target` will not get there without outside intervention.
+///
+/// A service that stops while waiting for it to run has failed to start, and a service that
+/// goes from stopping back to running has refused to stop.
+fn moved_away_from(
+    target: ServiceState,
+    previous: Option<ServiceState>,
+    current: ServiceState,
+) -> bool {
+    match target {
+        ServiceState::Running => current == ServiceState::Stopped,
+        ServiceState::Stopped => {
+            previous == Some(ServiceState::StopPending) && current == ServiceState::Running
+        }
+        _ => false,
+    }
+}
+
+/// Maps the result of querying an optional config component to `None` if the system does not
+/// support the component, or reports it as not set, and passes on any other error.
+fn optional_component<T>(result: crate::Result<T>) -> crate::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::Winapi(e))
+            if [
+                ERROR_INVALID_LEVEL,
+                ERROR_NOT_SUPPORTED,
+                ERROR_FILE_NOT_FOUND,
+            ]
+            .iter()
+            .any(|code| e.raw_os_error() == Some(*code as i32)) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The configuration cached by [`Service::config_cached`] and [`Service::full_config_cached`].
+#[derive(Default)]
+struct ConfigCache {
+    config: Option<ServiceConfig>,
+    full_config: Option<FullServiceConfig>,
+    /// Incremented on every invalidation, so that a query that started before an invalidation
+    /// does not store its possibly stale result.
+    generation: u64,
+}
+
+impl ConfigCache {
+    fn invalidate(&mut self) {
+        self.config = None;
+        self.full_config = None;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn store_config(&mut self, generation: u64, config: ServiceConfig) {
+        if self.generation == generation {
+            self.config = Some(config);
+        }
+    }
+
+    fn store_full_config(&mut self, generation: u64, full_config: FullServiceConfig) {
+        if self.generation == generation {
+            self.full_config = Some(full_config);
+        }
+    }
+}
+
+impl Service {
+    pub(crate) fn new(service_handle: ScHandle) -> Self {
+        Service {
+            service_handle,
+            config_cache: Mutex::new(ConfigCache::default()),
+        }
+    }
+
+    /// Provides access to the underlying system service handle
+    pub fn raw_handle(&self) -> Services::SC_HANDLE {
+        self.service_handle.raw_handle()
+    }
+
+    /// Start the service.
+    ///
+    /// # Errors
+    ///
+    /// Drivers do not support start arguments. Returns [`Error::LaunchArgumentsNotSupported`] when
+    /// passing arguments to a driver, provided that the service was opened with the
+    /// [`ServiceAccess::QUERY_STATUS`] access permission needed to determine the service type.
+    ///
     /// # Example
     ///
     /// ```rust,no_run
@@ -1474,6 +3271,11 @@ impl Service {
     /// # }
     /// ```
     pub fn start<S: AsRef<OsStr>>(&self, service_arguments: &[S]) -> crate::Result<()> {
+        // the system would otherwise silently ignore the arguments passed to a driver
+        if !service_arguments.is_empty() && self.is_driver() == Some(true) {
+            return Err(Error::LaunchArgumentsNotSupported);
+        }
+
         let wide_service_arguments = service_arguments
             .iter()
             .map(|s| {
@@ -1495,7 +3297,7 @@ impl Service {
         };
 
         if success == 0 {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
             Ok(())
         }
@@ -1506,6 +3308,199 @@ impl Service {
         self.send_control_command(ServiceControl::Stop)
     }
 
+    /// Stop the service, if it is running, and wait until it has stopped.
+    ///
+    /// Unlike [`Service::stop`], this succeeds if the service is already stopped, which makes it
+    /// suitable for idempotent uninstall and restart scripts. A service that is already stopping
+    /// is waited for.
+    ///
+    /// Required permissions: [`ServiceAccess::STOP`] and [`ServiceAccess::QUERY_STATUS`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Timeout`] if the service has not stopped within `timeout`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use windows_service::service::ServiceAccess;
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let my_service = manager.open_service(
+    ///     "my_service",
+    ///     ServiceAccess::STOP | ServiceAccess::QUERY_STATUS,
+    /// )?;
+    /// my_service.ensure_stopped(Duration::from_secs(30))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn ensure_stopped(&self, timeout: Duration) -> crate::Result<()> {
+        self.stop_and_wait(timeout, |_| {}).map(|_| ())
+    }
+
+    /// Stop the service, if it is running, and wait until it has stopped, reporting progress.
+    ///
+    /// This behaves like [`Service::ensure_stopped`], but calls `on_progress` with the status
+    /// queried on each poll, which lets the caller display progress based on the
+    /// [`ServiceStatus::checkpoint`] and [`ServiceStatus::wait_hint`] reported by the service.
+    /// Returns the status of the stopped service.
+    ///
+    /// Required permissions: [`ServiceAccess::STOP`] and [`ServiceAccess::QUERY_STATUS`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Timeout`] if the service has not stopped within `timeout`, and
+    /// [`Error::UnexpectedState`] if the service goes back to running while stopping.
+    pub fn stop_and_wait(
+        &self,
+        timeout: Duration,
+        mut on_progress: impl FnMut(&ServiceStatus),
+    ) -> crate::Result<ServiceStatus> {
+        match self.stop() {
+            Ok(_) => {}
+            Err(Error::Winapi(e)) if e.raw_os_error() == Some(ERROR_SERVICE_NOT_ACTIVE as i32) => {
+                let status = self.query_status()?;
+                on_progress(&status);
+                return Ok(status);
+            }
+            Err(Error::Winapi(e))
+                if e.raw_os_error() == Some(ERROR_SERVICE_CANNOT_ACCEPT_CTRL as i32) =>
+            {
+                // The service is in a pending state. Only wait if it is on its way down.
+                let state = self.state()?;
+                if state != ServiceState::StopPending && state != ServiceState::Stopped {
+                    return Err(Error::Winapi(e));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+        self.wait_for_state(ServiceState::Stopped, timeout, &mut on_progress)
+    }
+
+    /// Start the service and wait until it is running, reporting progress.
+    ///
+    /// `on_progress` is called with the status queried on each poll. Returns the status of the
+    /// running service.
+    ///
+    /// Required permissions: [`ServiceAccess::START`] and [`ServiceAccess::QUERY_STATUS`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Timeout`] if the service is not running within `timeout`, and
+    /// [`Error::UnexpectedState`] with the exit code of the service if it stops while starting.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::ffi::OsStr;
+    /// use std::time::Duration;
+    /// use windows_service::service::{ServiceAccess, ServiceState};
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let my_service = manager.open_service(
+    ///     "my_service",
+    ///     ServiceAccess::START | ServiceAccess::QUERY_STATUS,
+    /// )?;
+    /// my_service.start_and_wait(&[] as &[&OsStr], Duration::from_secs(30), |status| {
+    ///     if status.current_state == ServiceState::StartPending {
+    ///         println!("starting... checkpoint {}", status.checkpoint);
+    ///     }
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn start_and_wait<S: AsRef<OsStr>>(
+        &self,
+        service_arguments: &[S],
+        timeout: Duration,
+        mut on_progress: impl FnMut(&ServiceStatus),
+    ) -> crate::Result<ServiceStatus> {
+        self.start(service_arguments)?;
+        self.wait_for_state(ServiceState::Running, timeout, &mut on_progress)
+    }
+
+    /// Restart the service by stopping it, if it is running, and starting it again.
+    ///
+    /// Waits for the service to stop, tolerating a service that is already stopped, then starts
+    /// it with `service_arguments` and waits until it is running. `timeout` covers the whole
+    /// sequence. Returns the status of the running service.
+    ///
+    /// Required permissions: [`ServiceAccess::STOP`], [`ServiceAccess::START`] and
+    /// [`ServiceAccess::QUERY_STATUS`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Timeout`] if the service has not stopped and started again within
+    /// `timeout`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::ffi::OsStr;
+    /// use std::time::Duration;
+    /// use windows_service::service::ServiceAccess;
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let my_service = manager.open_service(
+    ///     "my_service",
+    ///     ServiceAccess::STOP | ServiceAccess::START | ServiceAccess::QUERY_STATUS,
+    /// )?;
+    /// my_service.restart(&[] as &[&OsStr], Duration::from_secs(30))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn restart<S: AsRef<OsStr>>(
+        &self,
+        service_arguments: &[S],
+        timeout: Duration,
+    ) -> crate::Result<ServiceStatus> {
+        let start = Instant::now();
+        self.ensure_stopped(timeout)?;
+        let remaining = timeout.saturating_sub(start.elapsed());
+        self.start_and_wait(service_arguments, remaining, |_| {})
+    }
+
+    /// Private helper that polls the service status until it reaches `state` or `timeout`
+    /// elapses, passing each queried status to `on_progress`. Returns the status in which the
+    /// service reached `state`, or an error as soon as it moves away from `state`, see
+    /// [`moved_away_from`].
+    fn wait_for_state(
+        &self,
+        state: ServiceState,
+        timeout: Duration,
+        on_progress: &mut dyn FnMut(&ServiceStatus),
+    ) -> crate::Result<ServiceStatus> {
+        let start = Instant::now();
+        let mut previous_state = None;
+        loop {
+            let status = self.query_status()?;
+            on_progress(&status);
+            if status.current_state == state {
+                return Ok(status);
+            }
+            if moved_away_from(state, previous_state, status.current_state) {
+                return Err(Error::UnexpectedState(status));
+            }
+            previous_state = Some(status.current_state);
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Err(Error::Timeout(status.current_state));
+            }
+            // Poll at a tenth of the wait hint, as recommended by the docs, within sane bounds.
+            let poll_interval = (status.wait_hint / 10)
+                .max(MIN_POLL_INTERVAL)
+                .min(MAX_POLL_INTERVAL);
+            thread::sleep(poll_interval.min(timeout - elapsed));
+        }
+    }
+
     /// Pause the service.
     ///
     /// # Example
@@ -1535,8 +3530,33 @@ impl Service {
         self.send_control_command(ServiceControl::UserEvent(code))
     }
 
+    /// Notify the service that its startup parameters have changed and that it should reread
+    /// them.
+    ///
+    /// The service has to accept [`ServiceControlAccept::PARAM_CHANGE`] and is delivered a
+    /// [`ServiceControl::ParamChange`] event.
+    ///
+    /// Required permission: [`ServiceAccess::PAUSE_CONTINUE`].
+    pub fn notify_param_change(&self) -> crate::Result<ServiceStatus> {
+        self.send_control_command(ServiceControl::ParamChange)
+    }
+
     /// Get the service status from the system.
     pub fn query_status(&self) -> crate::Result<ServiceStatus> {
+        ServiceStatus::from_raw_ex(self.query_status_process()?)
+            .map_err(|e| Error::ParseValue("service status", e))
+    }
+
+    /// Get the flags describing the process that hosts the service.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_STATUS`].
+    pub fn query_service_flags(&self) -> crate::Result<ServiceFlags> {
+        let raw_status = self.query_status_process()?;
+        Ok(ServiceFlags::from_bits_truncate(raw_status.dwServiceFlags))
+    }
+
+    /// Private helper to query the extended service status.
+    fn query_status_process(&self) -> crate::Result<Services::SERVICE_STATUS_PROCESS> {
         let mut raw_status = unsafe { mem::zeroed::<Services::SERVICE_STATUS_PROCESS>() };
         let mut bytes_needed: u32 = 0;
         let success = unsafe {
@@ -1549,13 +3569,53 @@ impl Service {
             )
         };
         if success == 0 {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
-            ServiceStatus::from_raw_ex(raw_status)
-                .map_err(|e| Error::ParseValue("service status", e))
+            Ok(raw_status)
         }
     }
 
+    /// Get the current state of the service from the system.
+    ///
+    /// This is a shorthand for reading [`ServiceStatus::current_state`] from
+    /// [`Service::query_status`].
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_STATUS`].
+    pub fn state(&self) -> crate::Result<ServiceState> {
+        Ok(self.query_status()?.current_state)
+    }
+
+    /// Returns `true` if the service is in the [`ServiceState::Running`] state.
+    ///
+    /// Services that are starting, stopping or paused are not considered running.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_STATUS`].
+    pub fn is_running(&self) -> crate::Result<bool> {
+        Ok(self.state()? == ServiceState::Running)
+    }
+
+    /// Get the time elapsed since the service process was started.
+    ///
+    /// Returns `None` when the service is not running. The uptime is derived from the creation
+    /// time of the service process, so for services that share a process with other services
+    /// this is the uptime of the hosting process.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_STATUS`]. Querying the creation time of a
+    /// process that belongs to another user may additionally require the calling process to run
+    /// elevated.
+    pub fn uptime(&self) -> crate::Result<Option<Duration>> {
+        let process_id = match self.query_status()?.process_id {
+            Some(process_id) => process_id,
+            None => return Ok(None),
+        };
+        let creation_time = process_creation_time(process_id).map_err(Error::winapi)?;
+        Ok(Some(
+            SystemTime::now()
+                .duration_since(creation_time)
+                .unwrap_or_default(),
+        ))
+    }
+
     /// Mark the service for deletion from the service control manager database.
     ///
     /// The database entry is not removed until all open handles to the service have been closed
@@ -1565,12 +3625,60 @@ impl Service {
     pub fn delete(&self) -> crate::Result<()> {
         let success = unsafe { Services::DeleteService(self.service_handle.raw_handle()) };
         if success == 0 {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
             Ok(())
         }
     }
 
+    /// Compare the service config stored by the system with the desired `service_info`.
+    ///
+    /// Returns the differences found by [`ServiceInfo::differences`], or an empty vector if the
+    /// stored config matches. Useful for verifying an installation after
+    /// [`ServiceManager::create_service`].
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::ffi::OsString;
+    /// use windows_service::service::{
+    ///     ServiceAccess, ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceType,
+    /// };
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CREATE_SERVICE)?;
+    /// let service_info = ServiceInfo {
+    ///     name: OsString::from("my_service"),
+    ///     display_name: OsString::from("My service"),
+    ///     service_type: ServiceType::OWN_PROCESS,
+    ///     start_type: ServiceStartType::OnDemand,
+    ///     error_control: ServiceErrorControl::Normal,
+    ///     executable_path: "C:\\my_service.exe".into(),
+    ///     launch_arguments: vec![],
+    ///     dependencies: vec![],
+    ///     account_name: None,
+    ///     account_password: None,
+    /// };
+    /// let service = manager.create_service(&service_info, ServiceAccess::QUERY_CONFIG)?;
+    /// for difference in service.verify_matches(&service_info)? {
+    ///     println!("config differs: {:?}", difference);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`ServiceManager::create_service`]: crate::service_manager::ServiceManager::create_service
+    pub fn verify_matches(
+        &self,
+        service_info: &ServiceInfo,
+    ) -> crate::Result<Vec<ConfigDifference>> {
+        service_info.differences(&self.query_config()?)
+    }
+
     /// Get the service config from the system.
     pub fn query_config(&self) -> crate::Result<ServiceConfig> {
         // As per docs, the maximum size of data buffer used by QueryServiceConfigW is 8K
@@ -1587,7 +3695,7 @@ impl Service {
         };
 
         if success == 0 {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
             unsafe {
                 let raw_config = data.as_ptr() as *const Services::QUERY_SERVICE_CONFIGW;
@@ -1596,21 +3704,121 @@ impl Service {
         }
     }
 
-    /// Update the service config.
-    /// Caveat: You cannot reset the account name/password by passing NULL.
+    /// Query the complete configuration of the service.
     ///
-    /// This implementation does not currently expose the full flexibility of the
-    /// `ChangeServiceConfigW` API. When calling the API it's possible to pass NULL in place of
-    /// any of the string arguments to indicate that they should not be updated.
+    /// The basic configuration is always queried, while each of the optional components is
+    /// `None` if the system reports it as unsupported or unset. This makes it possible to take a
+    /// snapshot across Windows versions that do not support all of the components.
     ///
-    /// If we wanted to support this we wouldn't be able to reuse the `ServiceInfo` struct.
-    pub fn change_config(&self, service_info: &ServiceInfo) -> crate::Result<()> {
-        let raw_info = RawServiceInfo::new(service_info)?;
-        let success = unsafe {
-            Services::ChangeServiceConfigW(
-                self.service_handle.raw_handle(),
-                raw_info.service_type,
-                raw_info.start_type,
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the components cannot be queried for another reason, for
+    /// instance because of insufficient permissions or a value that cannot be parsed.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceAccess;
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let my_service = manager.open_service("my_service", ServiceAccess::QUERY_CONFIG)?;
+    /// let full_config = my_service.query_full_config()?;
+    /// println!("{:?}", full_config.description);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query_full_config(&self) -> crate::Result<FullServiceConfig> {
+        Ok(FullServiceConfig {
+            config: self.query_config()?,
+            description: optional_component(self.query_description())?,
+            delayed_auto_start: optional_component(self.query_delayed_auto_start())?,
+            sid_type: optional_component(self.get_config_service_sid_info())?,
+            required_privileges: optional_component(self.query_required_privileges())?,
+            preshutdown_timeout: optional_component(self.query_preshutdown_timeout())?,
+            failure_actions: optional_component(self.get_failure_actions())?,
+            failure_flag: optional_component(self.get_failure_actions_on_non_crash_failures())?,
+            launch_protected: optional_component(self.query_launch_protected())?,
+            triggers: optional_component(self.query_triggers())?,
+        })
+    }
+
+    /// Get the service config, querying the system only the first time.
+    ///
+    /// The config is cached in this [`Service`] until it is changed through one of its methods,
+    /// such as [`Service::change_config`] or [`Service::set_start_type`], after which the next
+    /// call queries the system again. Changes made by other means, for instance by another
+    /// process or through [`Service::raw_handle`], are not detected. Use
+    /// [`Service::invalidate_config_cache`] to discard the cached config in that case.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn config_cached(&self) -> crate::Result<ServiceConfig> {
+        let generation = {
+            let cache = self.lock_config_cache();
+            if let Some(config) = &cache.config {
+                return Ok(config.clone());
+            }
+            cache.generation
+        };
+        let config = self.query_config()?;
+        self.lock_config_cache()
+            .store_config(generation, config.clone());
+        Ok(config)
+    }
+
+    /// Get the complete service config, querying the system only the first time.
+    ///
+    /// This caches the result of [`Service::query_full_config`] the same way as
+    /// [`Service::config_cached`] does for [`Service::query_config`].
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn full_config_cached(&self) -> crate::Result<FullServiceConfig> {
+        let generation = {
+            let cache = self.lock_config_cache();
+            if let Some(full_config) = &cache.full_config {
+                return Ok(full_config.clone());
+            }
+            cache.generation
+        };
+        let full_config = self.query_full_config()?;
+        self.lock_config_cache()
+            .store_full_config(generation, full_config.clone());
+        Ok(full_config)
+    }
+
+    /// Discard the config cached by [`Service::config_cached`] and
+    /// [`Service::full_config_cached`].
+    ///
+    /// The methods of [`Service`] that change the config do this automatically.
+    pub fn invalidate_config_cache(&self) {
+        self.lock_config_cache().invalidate();
+    }
+
+    fn lock_config_cache(&self) -> std::sync::MutexGuard<'_, ConfigCache> {
+        // The cache is always left in a consistent state, so a poisoned lock can be recovered.
+        self.config_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Update the service config.
+    /// Caveat: You cannot reset the account name/password by passing NULL.
+    ///
+    /// This implementation does not currently expose the full flexibility of the
+    /// `ChangeServiceConfigW` API. When calling the API it's possible to pass NULL in place of
+    /// any of the string arguments to indicate that they should not be updated.
+    ///
+    /// If we wanted to support this we wouldn't be able to reuse the `ServiceInfo` struct.
+    pub fn change_config(&self, service_info: &ServiceInfo) -> crate::Result<()> {
+        let raw_info = RawServiceInfo::new(service_info)?;
+        let success = unsafe {
+            Services::ChangeServiceConfigW(
+                self.service_handle.raw_handle(),
+                raw_info.service_type,
+                raw_info.start_type,
                 raw_info.error_control,
                 raw_info.launch_command.as_ptr(),
                 ptr::null(),     // load ordering group
@@ -1630,9 +3838,56 @@ impl Service {
                 raw_info.display_name.as_ptr(),
             )
         };
+        // Invalidate after the call, so that a query racing with it cannot cache the old config.
+        self.invalidate_config_cache();
+
+        if success == 0 {
+            Err(Error::winapi(io::Error::last_os_error()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Change the start type of the service, leaving the rest of the configuration unchanged.
+    ///
+    /// Unlike [`Service::change_config`], this does not require building a complete
+    /// [`ServiceInfo`] and cannot accidentally overwrite other settings.
+    ///
+    /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::{ServiceAccess, ServiceStartType};
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let service = manager.open_service("my_service", ServiceAccess::CHANGE_CONFIG)?;
+    /// service.set_start_type(ServiceStartType::Disabled)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_start_type(&self, start_type: ServiceStartType) -> crate::Result<()> {
+        let success = unsafe {
+            Services::ChangeServiceConfigW(
+                self.service_handle.raw_handle(),
+                Services::SERVICE_NO_CHANGE,
+                start_type.to_raw(),
+                Services::SERVICE_NO_CHANGE,
+                ptr::null(),     // binary path
+                ptr::null(),     // load ordering group
+                ptr::null_mut(), // tag id within the load ordering group
+                ptr::null(),     // dependencies
+                ptr::null(),     // account name
+                ptr::null(),     // account password
+                ptr::null(),     // display name
+            )
+        };
+        self.invalidate_config_cache();
 
         if success == 0 {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
             Ok(())
         }
@@ -1655,7 +3910,7 @@ impl Service {
                 Services::SERVICE_CONFIG_FAILURE_ACTIONS_FLAG,
                 &mut raw_failure_actions_flag,
             )
-            .map_err(Error::Winapi)
+            .map_err(Error::winapi)
         }
     }
 
@@ -1666,7 +3921,7 @@ impl Service {
 
         let raw_failure_actions_flag: Services::SERVICE_FAILURE_ACTIONS_FLAG = unsafe {
             self.query_config2(Services::SERVICE_CONFIG_FAILURE_ACTIONS_FLAG, &mut data)
-                .map_err(Error::Winapi)?
+                .map_err(Error::winapi)?
         };
         Ok(raw_failure_actions_flag.fFailureActionsOnNonCrashFailures != 0)
     }
@@ -1683,7 +3938,7 @@ impl Service {
         // such, we can get away with not explicitly creating a structure and
         // instead re-using `ServiceSidType` that is `repr(u32)`.
         unsafe { self.query_config2(Services::SERVICE_CONFIG_SERVICE_SID_INFO, &mut data) }
-            .map_err(Error::Winapi)
+            .map_err(Error::winapi)
     }
 
     /// Require the system to set the service's SID type information to the
@@ -1703,7 +3958,7 @@ impl Service {
                 Services::SERVICE_CONFIG_SERVICE_SID_INFO,
                 &mut service_sid_type,
             )
-            .map_err(Error::Winapi)
+            .map_err(Error::winapi)
         }
     }
 
@@ -1714,7 +3969,7 @@ impl Service {
 
             let raw_failure_actions: Services::SERVICE_FAILURE_ACTIONSW = self
                 .query_config2(Services::SERVICE_CONFIG_FAILURE_ACTIONS, &mut data)
-                .map_err(Error::Winapi)?;
+                .map_err(Error::winapi)?;
 
             ServiceFailureActions::from_raw(raw_failure_actions)
         }
@@ -1798,7 +4053,7 @@ impl Service {
                 Services::SERVICE_CONFIG_FAILURE_ACTIONS,
                 &mut raw_failure_actions,
             )
-            .map_err(Error::Winapi)
+            .map_err(Error::winapi)
         }
     }
 
@@ -1817,7 +4072,7 @@ impl Service {
                 Services::SERVICE_CONFIG_DESCRIPTION,
                 &mut service_description,
             )
-            .map_err(Error::Winapi)
+            .map_err(Error::winapi)
         }
     }
 
@@ -1837,7 +4092,7 @@ impl Service {
                 Services::SERVICE_CONFIG_DELAYED_AUTO_START_INFO,
                 &mut delayed,
             )
-            .map_err(Error::Winapi)
+            .map_err(Error::winapi)
         }
     }
 
@@ -1858,10 +4113,161 @@ impl Service {
         };
         unsafe {
             self.change_config2(Services::SERVICE_CONFIG_PRESHUTDOWN_INFO, &mut timeout)
-                .map_err(Error::Winapi)
+                .map_err(Error::winapi)
+        }
+    }
+
+    /// Set the events that start or stop the service, replacing the existing triggers. Pass an
+    /// empty slice to remove all triggers.
+    ///
+    /// Panics if a data item is too large for its size to fit in a `u32`.
+    ///
+    /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
+    pub fn set_triggers(&self, triggers: &[ServiceTrigger]) -> crate::Result<()> {
+        let mut subtypes: Vec<GUID> = triggers
+            .iter()
+            .map(|trigger| GUID::from_u128(trigger.subtype.unwrap_or(0)))
+            .collect();
+        // The data is only read by the system, the pointers are mutable to match the API.
+        let mut raw_data_items: Vec<Vec<Services::SERVICE_TRIGGER_SPECIFIC_DATA_ITEM>> = triggers
+            .iter()
+            .map(|trigger| {
+                trigger
+                    .data_items
+                    .iter()
+                    .map(|item| Services::SERVICE_TRIGGER_SPECIFIC_DATA_ITEM {
+                        dwDataType: item.data_type,
+                        cbData: u32::try_from(item.data.len()).expect("Too large data item"),
+                        pData: item.data.as_ptr() as *mut u8,
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut raw_triggers: Vec<Services::SERVICE_TRIGGER> = triggers
+            .iter()
+            .zip(subtypes.iter_mut())
+            .zip(raw_data_items.iter_mut())
+            .map(
+                |((trigger, subtype), data_items)| Services::SERVICE_TRIGGER {
+                    dwTriggerType: trigger.trigger_type,
+                    dwAction: trigger.action.to_raw(),
+                    pTriggerSubtype: if trigger.subtype.is_some() {
+                        subtype
+                    } else {
+                        ptr::null_mut()
+                    },
+                    cDataItems: data_items.len() as u32,
+                    pDataItems: if data_items.is_empty() {
+                        ptr::null_mut()
+                    } else {
+                        data_items.as_mut_ptr()
+                    },
+                },
+            )
+            .collect();
+        let mut trigger_info = Services::SERVICE_TRIGGER_INFO {
+            cTriggers: raw_triggers.len() as u32,
+            pTriggers: if raw_triggers.is_empty() {
+                ptr::null_mut()
+            } else {
+                raw_triggers.as_mut_ptr()
+            },
+            pReserved: ptr::null_mut(),
+        };
+
+        unsafe {
+            self.change_config2(Services::SERVICE_CONFIG_TRIGGER_INFO, &mut trigger_info)
+                .map_err(Error::winapi)
         }
     }
 
+    /// Private helper to query the service description.
+    pub(crate) fn query_description(&self) -> crate::Result<OsString> {
+        let mut data = vec![0u8; MAX_QUERY_BUFFER_SIZE];
+        let raw_description: Services::SERVICE_DESCRIPTIONW = unsafe {
+            self.query_config2(Services::SERVICE_CONFIG_DESCRIPTION, &mut data)
+                .map_err(Error::winapi)?
+        };
+        Ok(ptr::NonNull::new(raw_description.lpDescription)
+            .map(|wrapped_ptr| {
+                unsafe { WideCStr::from_ptr_str(wrapped_ptr.as_ptr()) }.to_os_string()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Private helper to query whether an auto-start service is delayed.
+    pub(crate) fn query_delayed_auto_start(&self) -> crate::Result<bool> {
+        let mut data = vec![0u8; MAX_QUERY_BUFFER_SIZE];
+        let raw_delayed: Services::SERVICE_DELAYED_AUTO_START_INFO = unsafe {
+            self.query_config2(Services::SERVICE_CONFIG_DELAYED_AUTO_START_INFO, &mut data)
+                .map_err(Error::winapi)?
+        };
+        Ok(raw_delayed.fDelayedAutostart != 0)
+    }
+
+    /// Private helper to query the privileges required by the service.
+    fn query_required_privileges(&self) -> crate::Result<Vec<OsString>> {
+        let mut data = vec![0u8; MAX_QUERY_BUFFER_SIZE];
+        unsafe {
+            let raw_privileges: Services::SERVICE_REQUIRED_PRIVILEGES_INFOW = self
+                .query_config2(Services::SERVICE_CONFIG_REQUIRED_PRIVILEGES_INFO, &mut data)
+                .map_err(Error::winapi)?;
+            Ok(double_nul_terminated::parse_str_ptr(
+                raw_privileges.pmszRequiredPrivileges,
+            ))
+        }
+    }
+
+    /// Private helper to query the preshutdown timeout.
+    fn query_preshutdown_timeout(&self) -> crate::Result<Duration> {
+        let mut data = vec![0u8; MAX_QUERY_BUFFER_SIZE];
+        let raw_timeout: Services::SERVICE_PRESHUTDOWN_INFO = unsafe {
+            self.query_config2(Services::SERVICE_CONFIG_PRESHUTDOWN_INFO, &mut data)
+                .map_err(Error::winapi)?
+        };
+        Ok(Duration::from_millis(
+            raw_timeout.dwPreshutdownTimeout as u64,
+        ))
+    }
+
+    /// Private helper to query the protection level the service is launched with.
+    fn query_launch_protected(&self) -> crate::Result<ServiceLaunchProtected> {
+        let mut data = vec![0u8; MAX_QUERY_BUFFER_SIZE];
+        let raw_launch_protected: Services::SERVICE_LAUNCH_PROTECTED_INFO = unsafe {
+            self.query_config2(Services::SERVICE_CONFIG_LAUNCH_PROTECTED, &mut data)
+                .map_err(Error::winapi)?
+        };
+        ServiceLaunchProtected::from_raw(raw_launch_protected.dwLaunchProtected)
+            .map_err(|e| Error::ParseValue("service launch protected", e))
+    }
+
+    /// Private helper to query the service triggers.
+    pub(crate) fn query_triggers(&self) -> crate::Result<Vec<ServiceTrigger>> {
+        let mut data = vec![0u8; MAX_QUERY_BUFFER_SIZE];
+        unsafe {
+            let raw_trigger_info: Services::SERVICE_TRIGGER_INFO = self
+                .query_config2(Services::SERVICE_CONFIG_TRIGGER_INFO, &mut data)
+                .map_err(Error::winapi)?;
+            if raw_trigger_info.pTriggers.is_null() {
+                return Ok(Vec::new());
+            }
+            (0..raw_trigger_info.cTriggers as usize)
+                .map(|i| {
+                    let raw_trigger = ptr::read_unaligned(raw_trigger_info.pTriggers.add(i));
+                    ServiceTrigger::from_raw(&raw_trigger)
+                })
+                .collect()
+        }
+    }
+
+    /// Private helper to determine whether the service is a driver. Returns `None` when the
+    /// service type cannot be queried.
+    fn is_driver(&self) -> Option<bool> {
+        self.query_status()
+            .ok()
+            .map(|status| status.service_type.is_driver())
+    }
+
     /// Private helper to send the control commands to the system.
     fn send_control_command(&self, command: ServiceControl) -> crate::Result<ServiceStatus> {
         let mut raw_status = unsafe { mem::zeroed::<Services::SERVICE_STATUS>() };
@@ -1874,7 +4280,7 @@ impl Service {
         };
 
         if success == 0 {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
             ServiceStatus::from_raw(raw_status).map_err(|e| Error::ParseValue("service status", e))
         }
@@ -1900,12 +4306,16 @@ impl Service {
     }
 
     /// Private helper to update the optional configuration parameters of windows services.
+    ///
+    /// This invalidates the cached config, see [`Service::config_cached`].
     unsafe fn change_config2<T>(&self, kind: u32, data: &mut T) -> io::Result<()> {
         let success = Services::ChangeServiceConfig2W(
             self.service_handle.raw_handle(),
             kind,
             data as *mut _ as *mut _,
         );
+        // The change may have been partially applied even if the call failed.
+        self.invalidate_config_cache();
 
         if success == 0 {
             Err(io::Error::last_os_error())
@@ -1918,6 +4328,53 @@ impl Service {
 /// The maximum size of data buffer used by QueryServiceConfigW and QueryServiceConfig2W is 8K
 const MAX_QUERY_BUFFER_SIZE: usize = 8 * 1024;
 
+/// The shortest interval between status queries when waiting for a state change.
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The longest interval between status queries when waiting for a state change.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The number of 100 nanosecond intervals between the `FILETIME` epoch (1601-01-01) and the Unix
+/// epoch (1970-01-01).
+const FILETIME_UNIX_EPOCH_OFFSET: u64 = 116_444_736_000_000_000;
+
+/// Query the system for the creation time of the process with the given id.
+fn process_creation_time(process_id: u32) -> io::Result<SystemTime> {
+    let process_handle = unsafe {
+        Threading::OpenProcess(Threading::PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id)
+    };
+    if process_handle.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut creation_time = unsafe { mem::zeroed::<FILETIME>() };
+    let mut exit_time = unsafe { mem::zeroed::<FILETIME>() };
+    let mut kernel_time = unsafe { mem::zeroed::<FILETIME>() };
+    let mut user_time = unsafe { mem::zeroed::<FILETIME>() };
+    let success = unsafe {
+        Threading::GetProcessTimes(
+            process_handle,
+            &mut creation_time,
+            &mut exit_time,
+            &mut kernel_time,
+            &mut user_time,
+        )
+    };
+    let result = if success == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(system_time_from_filetime(creation_time))
+    };
+    unsafe { CloseHandle(process_handle) };
+    result
+}
+
+fn system_time_from_filetime(filetime: FILETIME) -> SystemTime {
+    let intervals = (u64::from(filetime.dwHighDateTime) << 32) | u64::from(filetime.dwLowDateTime);
+    let since_unix_epoch = intervals.saturating_sub(FILETIME_UNIX_EPOCH_OFFSET);
+    UNIX_EPOCH + Duration::from_nanos(since_unix_epoch) * 100
+}
+
 fn to_wide_slice(
     s: Option<impl AsRef<OsStr>>,
 ) -> ::std::result::Result<Option<Vec<u16>>, ContainsNul<u16>> {
@@ -1935,6 +4392,9 @@ pub enum ParseRawError {
     InvalidInteger(u32),
     InvalidIntegerSigned(i32),
     InvalidGuid(String),
+    InvalidString(String),
+    /// The input has the given length, which is too short.
+    InvalidLength(usize),
 }
 
 impl std::error::Error for ParseRawError {}
@@ -1951,10 +4411,24 @@ impl std::fmt::Display for ParseRawError {
             Self::InvalidGuid(guid) => {
                 write!(f, "invalid GUID value for the target type: {}", guid)
             }
+            Self::InvalidString(s) => {
+                write!(f, "invalid string value for the target type: {}", s)
+            }
+            Self::InvalidLength(len) => {
+                write!(f, "invalid length for the target type: {}", len)
+            }
         }
     }
 }
 
+/// Returns the GUID in the same form as accepted by `GUID::from_u128`.
+fn u128_from_guid(guid: &GUID) -> u128 {
+    ((guid.data1 as u128) << 96)
+        | ((guid.data2 as u128) << 80)
+        | ((guid.data3 as u128) << 64)
+        | u64::from_be_bytes(guid.data4) as u128
+}
+
 fn string_from_guid(guid: &GUID) -> String {
     format!(
         "{:8X}-{:4X}-{:4X}-{:2X}{:2X}-{:2X}{:2X}{:2X}{:2X}{:2X}{:2X}",
@@ -2010,4 +4484,1018 @@ mod tests {
             ServiceDependency::Service(OsString::from("netlogon"))
         );
     }
+
+    #[test]
+    fn test_system_time_from_filetime() {
+        let unix_epoch = FILETIME {
+            dwLowDateTime: FILETIME_UNIX_EPOCH_OFFSET as u32,
+            dwHighDateTime: (FILETIME_UNIX_EPOCH_OFFSET >> 32) as u32,
+        };
+        assert_eq!(system_time_from_filetime(unix_epoch), UNIX_EPOCH);
+
+        let one_second_later = FILETIME {
+            dwLowDateTime: (FILETIME_UNIX_EPOCH_OFFSET + 10_000_000) as u32,
+            dwHighDateTime: ((FILETIME_UNIX_EPOCH_OFFSET + 10_000_000) >> 32) as u32,
+        };
+        assert_eq!(
+            system_time_from_filetime(one_second_later),
+            UNIX_EPOCH + Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_service_type_category() {
+        assert_eq!(
+            ServiceType::KERNEL_DRIVER.category(),
+            ServiceCategory::Driver
+        );
+        assert_eq!(
+            ServiceType::FILE_SYSTEM_DRIVER.category(),
+            ServiceCategory::Driver
+        );
+        assert_eq!(
+            (ServiceType::OWN_PROCESS | ServiceType::INTERACTIVE_PROCESS).category(),
+            ServiceCategory::Win32OwnProcess
+        );
+        assert_eq!(
+            ServiceType::SHARE_PROCESS.category(),
+            ServiceCategory::Win32SharedProcess
+        );
+        assert_eq!(
+            ServiceType::USER_OWN_PROCESS.category(),
+            ServiceCategory::UserService
+        );
+        assert_eq!(
+            ServiceType::USER_SHARE_PROCESS.category(),
+            ServiceCategory::UserService
+        );
+        assert_eq!(ServiceType::empty().category(), ServiceCategory::Unknown);
+    }
+
+    #[test]
+    fn test_split_command_line() {
+        let path = |path: &str, quoted| CommandLineExecutable::Path {
+            path: OsString::from(path),
+            quoted,
+        };
+        assert_eq!(
+            split_command_line(OsStr::new(r#""C:\Program Files\app.exe" --flag"#)),
+            path(r"C:\Program Files\app.exe", true)
+        );
+        assert_eq!(
+            split_command_line(OsStr::new(r"C:\Program Files\My App\app.EXE --flag")),
+            path(r"C:\Program Files\My App\app.EXE", false)
+        );
+        assert_eq!(
+            split_command_line(OsStr::new(r"C:\Windows\system32\svchost.exe -k netsvcs")),
+            path(r"C:\Windows\system32\svchost.exe", false)
+        );
+        assert_eq!(
+            split_command_line(OsStr::new(r"C:\tools\app.exenot\daemon  run ")),
+            CommandLineExecutable::Ambiguous(vec![
+                OsString::from(r"C:\tools\app.exenot\daemon"),
+                OsString::from(r"C:\tools\app.exenot\daemon  run"),
+            ])
+        );
+    }
+
+    fn service_config(service_type: ServiceType, executable_path: &str) -> ServiceConfig {
+        ServiceConfig {
+            service_type,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from(executable_path),
+            load_order_group: None,
+            tag_id: 0,
+            dependencies: vec![],
+            account_name: None,
+            display_name: OsString::from("My service"),
+        }
+    }
+
+    #[test]
+    fn test_executable_file_paths_without_exe() {
+        let config = service_config(
+            ServiceType::OWN_PROCESS,
+            r"C:\Program Files\My Svc\svc --flag",
+        );
+        assert_eq!(
+            config.executable_file_paths(),
+            vec![
+                PathBuf::from(r"C:\Program"),
+                PathBuf::from(r"C:\Program.exe"),
+                PathBuf::from(r"C:\Program Files\My"),
+                PathBuf::from(r"C:\Program Files\My.exe"),
+                PathBuf::from(r"C:\Program Files\My Svc\svc"),
+                PathBuf::from(r"C:\Program Files\My Svc\svc.exe"),
+                PathBuf::from(r"C:\Program Files\My Svc\svc --flag"),
+                PathBuf::from(r"C:\Program Files\My Svc\svc --flag.exe"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_executable_exists_with_spaces_without_exe() {
+        let dir = std::env::temp_dir().join(format!("windows-service test {}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("svc.exe"), b"").unwrap();
+        std::fs::write(dir.join("launch.bat"), b"").unwrap();
+
+        let path = |name: &str| format!(r"{}\{} --flag", dir.display(), name);
+        assert!(service_config(ServiceType::OWN_PROCESS, &path("svc")).executable_exists());
+        assert!(service_config(ServiceType::OWN_PROCESS, &path("launch.bat")).executable_exists());
+        assert!(!service_config(ServiceType::OWN_PROCESS, &path("missing")).executable_exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_has_unquoted_path_vulnerability() {
+        let vulnerable =
+            |path| service_config(ServiceType::OWN_PROCESS, path).has_unquoted_path_vulnerability();
+        assert!(vulnerable(r"C:\Program Files\My App\service.exe"));
+        assert!(!vulnerable(
+            r#""C:\Program Files\My App\service.exe" --flag"#
+        ));
+        assert!(!vulnerable(r"C:\tools\service.exe --log C:\logs"));
+
+        // Without `.exe`, a path separator after whitespace means that the path continues.
+        assert!(vulnerable(r"C:\Program Files\My Svc\svc --flag"));
+        assert!(vulnerable(r"C:\Program Files\My Svc\launch.bat"));
+        assert!(vulnerable(r"C:\Program Files\My Svc\helper.sys --flag"));
+        assert!(!vulnerable(r"C:\tools\svc --flag"));
+        assert!(!vulnerable(r"C:\tools\launch.bat"));
+
+        assert!(!service_config(
+            ServiceType::KERNEL_DRIVER,
+            r"C:\Program Files\My Svc\driver.sys"
+        )
+        .has_unquoted_path_vulnerability());
+    }
+
+    #[test]
+    fn test_service_status_validate_against() {
+        let status = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::Running,
+            controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::SESSION_CHANGE,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        };
+        assert!(status.validate_against(ServiceType::OWN_PROCESS).is_ok());
+        assert!(status
+            .validate_against(ServiceType::OWN_PROCESS | ServiceType::INTERACTIVE_PROCESS)
+            .is_ok());
+        assert!(matches!(
+            status.validate_against(ServiceType::SHARE_PROCESS),
+            Err(Error::InvalidServiceStatus(_))
+        ));
+        assert!(matches!(
+            status.validate_against(ServiceType::USER_OWN_PROCESS),
+            Err(Error::InvalidServiceStatus(_))
+        ));
+        assert!(matches!(
+            status.validate_against(ServiceType::KERNEL_DRIVER),
+            Err(Error::InvalidServiceStatus(_))
+        ));
+
+        let user_status = ServiceStatus {
+            service_type: ServiceType::USER_SHARE_PROCESS,
+            controls_accepted: ServiceControlAccept::SESSION_CHANGE
+                | ServiceControlAccept::HARDWARE_PROFILE_CHANGE,
+            ..status
+        };
+        assert!(matches!(
+            user_status.validate_against(ServiceType::USER_SHARE_PROCESS),
+            Err(Error::InvalidServiceStatus(_))
+        ));
+    }
+
+    #[test]
+    fn test_service_control_accept_supported_by() {
+        assert_eq!(
+            ServiceControlAccept::supported_by(ServiceType::OWN_PROCESS),
+            ServiceControlAccept::all()
+        );
+        assert_eq!(
+            ServiceControlAccept::supported_by(ServiceType::FILE_SYSTEM_DRIVER),
+            ServiceControlAccept::empty()
+        );
+        let user_supported = ServiceControlAccept::supported_by(ServiceType::USER_OWN_PROCESS);
+        assert!(user_supported
+            .contains(ServiceControlAccept::STOP | ServiceControlAccept::SESSION_CHANGE));
+        assert!(!user_supported.intersects(ServiceControlAccept::UNSUPPORTED_BY_USER_SERVICES));
+    }
+
+    #[test]
+    fn test_service_exit_code_from_raw() {
+        let mut raw_status = unsafe { mem::zeroed::<Services::SERVICE_STATUS>() };
+        assert_eq!(
+            ServiceExitCode::from(&raw_status),
+            ServiceExitCode::NO_ERROR
+        );
+        assert!(ServiceExitCode::from(&raw_status).is_success());
+
+        raw_status.dwWin32ExitCode = 5;
+        raw_status.dwServiceSpecificExitCode = 7;
+        assert_eq!(
+            ServiceExitCode::from(&raw_status),
+            ServiceExitCode::Win32(5)
+        );
+
+        raw_status.dwWin32ExitCode = ERROR_SERVICE_SPECIFIC_ERROR;
+        assert_eq!(
+            ServiceExitCode::from(&raw_status),
+            ServiceExitCode::ServiceSpecific(7)
+        );
+
+        raw_status.dwServiceSpecificExitCode = 0;
+        let exit_code = ServiceExitCode::from(&raw_status);
+        assert_eq!(exit_code, ServiceExitCode::ServiceSpecific(0));
+        assert!(!exit_code.is_success());
+    }
+
+    #[test]
+    fn test_service_exit_code_round_trip() {
+        for exit_code in [
+            ServiceExitCode::NO_ERROR,
+            ServiceExitCode::Win32(5),
+            ServiceExitCode::ServiceSpecific(0),
+            ServiceExitCode::ServiceSpecific(42),
+        ] {
+            let mut raw_status = unsafe { mem::zeroed::<Services::SERVICE_STATUS>() };
+            exit_code.copy_to(&mut raw_status);
+            assert_eq!(ServiceExitCode::from(&raw_status), exit_code);
+        }
+    }
+
+    #[test]
+    fn test_service_config_semantically_eq() {
+        let config = ServiceConfig {
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from(r"C:\service.exe"),
+            load_order_group: None,
+            tag_id: 0,
+            dependencies: vec![ServiceDependency::Service(OsString::from("Tcpip"))],
+            account_name: Some(OsString::from("LocalSystem")),
+            display_name: OsString::from("My service"),
+        };
+        let other = ServiceConfig {
+            tag_id: 3,
+            ..config.clone()
+        };
+        assert_ne!(config, other);
+        assert!(config.semantically_eq(&other));
+
+        let other = ServiceConfig {
+            start_type: ServiceStartType::Disabled,
+            ..config.clone()
+        };
+        assert!(!config.semantically_eq(&other));
+    }
+
+    #[test]
+    fn test_service_status_semantically_eq() {
+        let status = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::Running,
+            controls_accepted: ServiceControlAccept::STOP,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: Some(1234),
+        };
+        let other = ServiceStatus {
+            process_id: Some(5678),
+            ..status.clone()
+        };
+        assert_ne!(status, other);
+        assert!(status.semantically_eq(&other));
+
+        let other = ServiceStatus {
+            current_state: ServiceState::Stopped,
+            process_id: None,
+            ..status.clone()
+        };
+        assert!(!status.semantically_eq(&other));
+    }
+
+    #[test]
+    fn test_service_action_type_own_restart() {
+        assert_eq!(ServiceActionType::OwnRestart.to_raw(), 4);
+        assert_eq!(
+            ServiceActionType::from_raw(4).unwrap(),
+            ServiceActionType::OwnRestart
+        );
+        assert!(ServiceActionType::from_raw(5).is_err());
+    }
+
+    #[test]
+    fn test_strip_prefix_ignore_ascii_case() {
+        let path: Vec<u16> = OsStr::new(r"\systemroot\System32\drivers\example.sys")
+            .encode_wide()
+            .collect();
+        let expected: Vec<u16> = OsStr::new(r"System32\drivers\example.sys")
+            .encode_wide()
+            .collect();
+        assert_eq!(
+            strip_prefix_ignore_ascii_case(&path, r"\SystemRoot\"),
+            Some(&expected[..])
+        );
+        assert_eq!(strip_prefix_ignore_ascii_case(&path, r"\??\"), None);
+    }
+
+    #[test]
+    fn test_prefer_preshutdown() {
+        assert_eq!(
+            (ServiceControlAccept::STOP
+                | ServiceControlAccept::SHUTDOWN
+                | ServiceControlAccept::PRESHUTDOWN)
+                .prefer_preshutdown(),
+            ServiceControlAccept::STOP | ServiceControlAccept::PRESHUTDOWN
+        );
+        assert_eq!(
+            (ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN).prefer_preshutdown(),
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+        );
+    }
+
+    #[test]
+    fn test_service_account_group_managed() {
+        let account = ServiceAccount::GroupManaged {
+            name: OsString::from(r"DOMAIN\my_gmsa"),
+        };
+        assert_eq!(
+            account.account_name(),
+            Some(OsString::from(r"DOMAIN\my_gmsa$"))
+        );
+        assert_eq!(account.account_password(), None);
+
+        let account = ServiceAccount::GroupManaged {
+            name: OsString::from(r"DOMAIN\my_gmsa$"),
+        };
+        assert_eq!(
+            account.account_name(),
+            Some(OsString::from(r"DOMAIN\my_gmsa$"))
+        );
+    }
+
+    #[test]
+    fn test_u128_from_guid() {
+        let value = 0x1ce20aba_9851_4421_9430_1ddeb766e809;
+        assert_eq!(u128_from_guid(&GUID::from_u128(value)), value);
+    }
+
+    fn service_info(service_type: ServiceType, executable_path: &str) -> ServiceInfo {
+        ServiceInfo {
+            name: OsString::from("test"),
+            display_name: OsString::from("Test"),
+            service_type,
+            start_type: ServiceStartType::OnDemand,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from(executable_path),
+            launch_arguments: vec![],
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+        }
+    }
+
+    #[test]
+    fn test_service_info_validate() {
+        let own_process = ServiceType::OWN_PROCESS;
+        assert!(service_info(own_process, r"C:\Program Files\test.exe")
+            .validate()
+            .is_ok());
+        assert!(matches!(
+            service_info(own_process, "test.exe").validate(),
+            Err(Error::RelativeExecutablePath(_))
+        ));
+        assert!(matches!(
+            service_info(own_process, r"bin\test.exe").validate(),
+            Err(Error::RelativeExecutablePath(_))
+        ));
+        assert!(
+            service_info(ServiceType::KERNEL_DRIVER, r"System32\drivers\test.sys")
+                .validate()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_service_controls_by_accept_covers_all_flags() {
+        let mut covered = ServiceControlAccept::empty();
+        for (accept, controls) in SERVICE_CONTROLS_BY_ACCEPT {
+            assert_eq!(accept.bits().count_ones(), 1);
+            assert!(!covered.intersects(*accept));
+            assert!(!controls.is_empty());
+            covered |= *accept;
+        }
+        assert_eq!(covered, ServiceControlAccept::all());
+    }
+
+    #[test]
+    fn test_service_control_required_accept() {
+        assert_eq!(
+            ServiceControl::Stop.required_accept(),
+            Some(ServiceControlAccept::STOP)
+        );
+        assert_eq!(
+            ServiceControl::Continue.required_accept(),
+            Some(ServiceControlAccept::PAUSE_CONTINUE)
+        );
+        assert_eq!(
+            ServiceControl::NetBindRemove.required_accept(),
+            Some(ServiceControlAccept::NETBIND_CHANGE)
+        );
+        assert_eq!(ServiceControl::Interrogate.required_accept(), None);
+        assert_eq!(
+            ServiceControl::UserEvent(UserEventCode::from_raw(128).unwrap()).required_accept(),
+            None
+        );
+    }
+
+    fn config_for(info: &ServiceInfo) -> ServiceConfig {
+        ServiceConfig {
+            service_type: info.service_type,
+            start_type: info.start_type,
+            error_control: info.error_control,
+            executable_path: PathBuf::from(
+                RawServiceInfo::new(info)
+                    .unwrap()
+                    .launch_command
+                    .to_os_string(),
+            ),
+            load_order_group: None,
+            tag_id: 0,
+            dependencies: info.dependencies.clone(),
+            account_name: Some(OsString::from("LocalSystem")),
+            display_name: info.display_name.clone(),
+        }
+    }
+
+    #[test]
+    fn test_service_info_differences() {
+        let mut info = service_info(ServiceType::OWN_PROCESS, r"C:\Program Files\test.exe");
+        info.launch_arguments = vec![OsString::from("--flag")];
+        let mut config = config_for(&info);
+        assert_eq!(info.differences(&config).unwrap(), vec![]);
+
+        config.account_name = Some(OsString::from("localsystem"));
+        config.tag_id = 3;
+        assert_eq!(info.differences(&config).unwrap(), vec![]);
+
+        config.start_type = ServiceStartType::AutoStart;
+        config.executable_path = PathBuf::from(r"C:\Program Files\test.exe");
+        assert_eq!(
+            info.differences(&config).unwrap(),
+            vec![
+                ConfigDifference::StartType {
+                    expected: ServiceStartType::OnDemand,
+                    actual: ServiceStartType::AutoStart,
+                },
+                ConfigDifference::LaunchCommand {
+                    expected: OsString::from(r#""C:\Program Files\test.exe" --flag"#),
+                    actual: OsString::from(r"C:\Program Files\test.exe"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_service_state_can_transition_to() {
+        use ServiceState::*;
+        assert!(Stopped.can_transition_to(StartPending));
+        assert!(StartPending.can_transition_to(StartPending));
+        assert!(StartPending.can_transition_to(Running));
+        assert!(Running.can_transition_to(StopPending));
+        assert!(Running.can_transition_to(Stopped));
+        assert!(StopPending.can_transition_to(Stopped));
+        assert!(PausePending.can_transition_to(Running));
+        assert!(Paused.can_transition_to(ContinuePending));
+
+        assert!(!Stopped.can_transition_to(Paused));
+        assert!(!Stopped.can_transition_to(Running));
+        assert!(!StopPending.can_transition_to(Running));
+        assert!(!Running.can_transition_to(Paused));
+        assert!(!Paused.can_transition_to(Running));
+    }
+
+    #[test]
+    fn test_power_broadcast_setting_battery_percent() {
+        assert_eq!(
+            PowerBroadcastSetting::BatteryPercentageRemaining(42).battery_percent(),
+            Some(42)
+        );
+        assert_eq!(
+            PowerBroadcastSetting::BatteryPercentageRemaining(300).battery_percent(),
+            Some(100)
+        );
+        assert_eq!(
+            PowerBroadcastSetting::IdleBackgroundTask.battery_percent(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_service_access_display() {
+        assert_eq!(
+            (ServiceAccess::QUERY_STATUS | ServiceAccess::START).to_string(),
+            "QUERY_STATUS | START"
+        );
+        assert_eq!(ServiceAccess::ALL_ACCESS.to_string(), "ALL_ACCESS");
+    }
+
+    #[test]
+    fn test_service_status_builder() {
+        let status = ServiceStatus::builder(ServiceType::OWN_PROCESS, ServiceState::StopPending)
+            .checkpoint(2)
+            .wait_hint(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert_eq!(status.checkpoint, 2);
+        assert_eq!(status.wait_hint, Duration::from_secs(5));
+        assert!(
+            ServiceStatus::builder(ServiceType::OWN_PROCESS, ServiceState::Paused)
+                .controls_accepted(
+                    ServiceControlAccept::STOP | ServiceControlAccept::PAUSE_CONTINUE
+                )
+                .build()
+                .is_ok()
+        );
+
+        assert!(matches!(
+            ServiceStatus::builder(ServiceType::OWN_PROCESS, ServiceState::Stopped)
+                .controls_accepted(ServiceControlAccept::PAUSE_CONTINUE)
+                .build(),
+            Err(Error::InvalidServiceStatus(_))
+        ));
+        assert!(matches!(
+            ServiceStatus::builder(ServiceType::OWN_PROCESS, ServiceState::Running)
+                .checkpoint(1)
+                .build(),
+            Err(Error::InvalidServiceStatus(_))
+        ));
+        assert!(matches!(
+            ServiceStatus::builder(ServiceType::OWN_PROCESS, ServiceState::Paused)
+                .wait_hint(Duration::from_millis(1))
+                .build(),
+            Err(Error::InvalidServiceStatus(_))
+        ));
+    }
+
+    #[test]
+    fn test_service_control_from_raw_without_event_data() {
+        let simple_controls = [
+            (Services::SERVICE_CONTROL_STOP, ServiceControl::Stop),
+            (
+                Services::SERVICE_CONTROL_INTERROGATE,
+                ServiceControl::Interrogate,
+            ),
+            (Services::SERVICE_CONTROL_SHUTDOWN, ServiceControl::Shutdown),
+            (
+                Services::SERVICE_CONTROL_PRESHUTDOWN,
+                ServiceControl::Preshutdown,
+            ),
+            (Services::SERVICE_CONTROL_PAUSE, ServiceControl::Pause),
+            (Services::SERVICE_CONTROL_CONTINUE, ServiceControl::Continue),
+        ];
+        for (raw, expected) in simple_controls {
+            let control =
+                unsafe { ServiceControl::from_raw(raw, u32::MAX, ptr::null_mut()) }.unwrap();
+            assert_eq!(control, expected);
+        }
+        // An unknown session change reason is rejected before the event data is read.
+        assert!(unsafe {
+            ServiceControl::from_raw(
+                Services::SERVICE_CONTROL_SESSIONCHANGE,
+                u32::MAX,
+                ptr::null_mut(),
+            )
+        }
+        .is_err());
+    }
+
+    #[test]
+    fn test_service_control_from_raw_unknown() {
+        let control = unsafe { ServiceControl::from_raw(0x7f, 0, ptr::null_mut()) }.unwrap();
+        assert_eq!(control, ServiceControl::Unknown(0x7f));
+        assert_eq!(control.raw_service_control_type(), 0x7f);
+        assert_eq!(control.required_accept(), None);
+
+        assert!(matches!(
+            unsafe { ServiceControl::from_raw(200, 0, ptr::null_mut()) },
+            Ok(ServiceControl::UserEvent(_))
+        ));
+        assert!(unsafe { ServiceControl::from_raw(256, 0, ptr::null_mut()) }.is_err());
+    }
+
+    #[test]
+    fn test_service_error_control_round_trip() {
+        for raw in [
+            Services::SERVICE_ERROR_CRITICAL,
+            Services::SERVICE_ERROR_IGNORE,
+            Services::SERVICE_ERROR_NORMAL,
+            Services::SERVICE_ERROR_SEVERE,
+            0x42,
+        ] {
+            assert_eq!(ServiceErrorControl::from(raw).to_raw(), raw);
+        }
+        assert_eq!(
+            ServiceErrorControl::from(0x42),
+            ServiceErrorControl::Unknown(0x42)
+        );
+        assert_eq!(
+            ServiceErrorControl::Unknown(Services::SERVICE_ERROR_NORMAL).normalize(),
+            ServiceErrorControl::Normal
+        );
+        assert_eq!(
+            ServiceErrorControl::Unknown(0x42).normalize(),
+            ServiceErrorControl::Unknown(0x42)
+        );
+    }
+
+    #[test]
+    fn test_service_status_to_raw_clears_controls_when_stopped() {
+        let mut status = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::Running,
+            controls_accepted: ServiceControlAccept::STOP,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        };
+        assert_eq!(
+            status.to_raw().dwControlsAccepted,
+            Services::SERVICE_ACCEPT_STOP
+        );
+
+        status.current_state = ServiceState::Stopped;
+        assert_eq!(status.to_raw().dwControlsAccepted, 0);
+    }
+
+    #[test]
+    fn test_service_action_display() {
+        let action = |action_type, delay| ServiceAction {
+            action_type,
+            delay: Duration::from_millis(delay),
+        };
+        assert_eq!(
+            action(ServiceActionType::Restart, 5000).to_string(),
+            "Restart after 5 seconds"
+        );
+        assert_eq!(
+            action(ServiceActionType::RunCommand, 90_000).to_string(),
+            "Run command after 90 seconds"
+        );
+        assert_eq!(
+            action(ServiceActionType::Reboot, 1500).to_string(),
+            "Reboot after 1500 milliseconds"
+        );
+        assert_eq!(
+            action(ServiceActionType::Restart, 0).to_string(),
+            "Restart immediately"
+        );
+        assert_eq!(
+            action(ServiceActionType::None, 1000).to_string(),
+            "No action"
+        );
+    }
+
+    #[test]
+    fn test_service_failure_actions_summary() {
+        let restart = ServiceAction {
+            action_type: ServiceActionType::Restart,
+            delay: Duration::from_secs(5),
+        };
+        let run_command = ServiceAction {
+            action_type: ServiceActionType::RunCommand,
+            delay: Duration::from_secs(10),
+        };
+        let mut failure_actions = ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(24 * 60 * 60)),
+            reboot_msg: None,
+            command: None,
+            actions: Some(vec![restart.clone(), restart, run_command]),
+        };
+        assert_eq!(
+            failure_actions.summary(),
+            "Restart x2 then run command; reset after 1 day"
+        );
+
+        failure_actions.reset_period = ServiceFailureResetPeriod::Never;
+        assert_eq!(
+            failure_actions.summary(),
+            "Restart x2 then run command; never reset"
+        );
+
+        failure_actions.actions = None;
+        assert_eq!(failure_actions.summary(), "No actions");
+    }
+
+    #[test]
+    fn test_account_sid_well_known() {
+        let mut config = ServiceConfig {
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::OnDemand,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from(r"C:\service.exe"),
+            load_order_group: None,
+            tag_id: 0,
+            dependencies: vec![],
+            account_name: None,
+            display_name: OsString::from("Service"),
+        };
+        let sid = |config: &ServiceConfig| config.account_sid().unwrap().map(|sid| sid.to_string());
+
+        assert_eq!(sid(&config).as_deref(), Some("S-1-5-18"));
+        config.account_name = Some(OsString::from("localsystem"));
+        assert_eq!(sid(&config).as_deref(), Some("S-1-5-18"));
+        config.account_name = Some(OsString::from(r"NT AUTHORITY\LocalService"));
+        assert_eq!(sid(&config).as_deref(), Some("S-1-5-19"));
+        config.account_name = Some(OsString::from(r"nt authority\networkservice"));
+        assert_eq!(sid(&config).as_deref(), Some("S-1-5-20"));
+
+        config.service_type = ServiceType::KERNEL_DRIVER;
+        assert_eq!(sid(&config), None);
+    }
+
+    #[test]
+    fn test_replace_system_directory() {
+        let system_root = OsStr::new(r"C:\Windows");
+        assert_eq!(
+            replace_system_directory(
+                Path::new(r"c:\windows\system32\svchost.exe"),
+                system_root,
+                "System32",
+                "Sysnative"
+            ),
+            Some(PathBuf::from(r"C:\Windows\Sysnative\svchost.exe"))
+        );
+        assert_eq!(
+            replace_system_directory(
+                Path::new(r"C:\Windows\System32"),
+                system_root,
+                "System32",
+                "Sysnative"
+            ),
+            Some(PathBuf::from(r"C:\Windows\Sysnative"))
+        );
+        assert_eq!(
+            replace_system_directory(
+                Path::new(r"C:\Windows\System32x\app.exe"),
+                system_root,
+                "System32",
+                "Sysnative"
+            ),
+            None
+        );
+        assert_eq!(
+            replace_system_directory(
+                Path::new(r"C:\Program Files\app.exe"),
+                system_root,
+                "System32",
+                "Sysnative"
+            ),
+            None
+        );
+        assert_eq!(
+            replace_system_directory(
+                Path::new(r"C:\Windows\sysnative\drivers\app.sys"),
+                system_root,
+                "Sysnative",
+                "System32"
+            ),
+            Some(PathBuf::from(r"C:\Windows\System32\drivers\app.sys"))
+        );
+    }
+
+    #[test]
+    fn test_full_service_config_warnings() {
+        let mut config = FullServiceConfig {
+            config: ServiceConfig {
+                service_type: ServiceType::OWN_PROCESS,
+                start_type: ServiceStartType::AutoStart,
+                error_control: ServiceErrorControl::Normal,
+                executable_path: PathBuf::from(r"C:\service.exe"),
+                load_order_group: None,
+                tag_id: 0,
+                dependencies: vec![],
+                account_name: None,
+                display_name: OsString::from("Service"),
+            },
+            description: None,
+            delayed_auto_start: Some(true),
+            sid_type: None,
+            required_privileges: None,
+            preshutdown_timeout: None,
+            failure_actions: None,
+            failure_flag: None,
+            launch_protected: None,
+            triggers: Some(vec![]),
+        };
+        assert!(config.warnings().is_empty());
+
+        config.triggers = Some(vec![ServiceTrigger {
+            trigger_type: Services::SERVICE_TRIGGER_TYPE_IP_ADDRESS_AVAILABILITY,
+            action: ServiceTriggerAction::Start,
+            subtype: None,
+            data_items: vec![],
+        }]);
+        assert_eq!(
+            config.warnings(),
+            vec![ConfigWarning::DelayedAutoStartWithTriggers]
+        );
+
+        config.delayed_auto_start = Some(false);
+        assert!(config.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_service_status_bytes_round_trip() {
+        let status = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::Running,
+            controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            exit_code: ServiceExitCode::ServiceSpecific(42),
+            checkpoint: 3,
+            wait_hint: Duration::from_secs(5),
+            process_id: Some(1234),
+        };
+        let bytes = status.to_bytes();
+        assert_eq!(bytes[0], ServiceStatus::BYTES_VERSION);
+        assert_eq!(ServiceStatus::from_bytes(&bytes).unwrap(), status);
+
+        // Fields appended by newer versions are ignored.
+        let mut extended = bytes.to_vec();
+        extended.extend_from_slice(&[0xff; 8]);
+        assert_eq!(ServiceStatus::from_bytes(&extended).unwrap(), status);
+
+        assert!(matches!(
+            ServiceStatus::from_bytes(&bytes[..ServiceStatus::BYTES_LEN - 1]),
+            Err(ParseRawError::InvalidLength(35))
+        ));
+        let mut unknown_version = bytes;
+        unknown_version[0] = 2;
+        assert!(matches!(
+            ServiceStatus::from_bytes(&unknown_version),
+            Err(ParseRawError::InvalidInteger(2))
+        ));
+    }
+
+    #[test]
+    fn test_user_service_template_name() {
+        assert_eq!(
+            user_service_template_name("MyService_3a5f1"),
+            Some(OsString::from("MyService"))
+        );
+        assert_eq!(
+            user_service_template_name("My_Service_3A5F1"),
+            Some(OsString::from("My_Service"))
+        );
+        assert_eq!(user_service_template_name("MyService"), None);
+        assert_eq!(user_service_template_name("MyService_"), None);
+        assert_eq!(user_service_template_name("MyService_xyz"), None);
+        assert_eq!(user_service_template_name("_3a5f1"), None);
+
+        let instance_type = ServiceType::USER_OWN_PROCESS | ServiceType::USER_SERVICE_INSTANCE;
+        assert!(instance_type.is_user_service_instance());
+        assert!(!ServiceType::USER_OWN_PROCESS.is_user_service_instance());
+        assert!(!ServiceType::OWN_PROCESS.is_user_service_instance());
+    }
+
+    #[test]
+    fn test_validate_against_user_service_controls() {
+        let status = |controls_accepted| ServiceStatus {
+            service_type: ServiceType::USER_OWN_PROCESS,
+            current_state: ServiceState::Running,
+            controls_accepted,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        };
+        let instance_type = ServiceType::USER_OWN_PROCESS | ServiceType::USER_SERVICE_INSTANCE;
+
+        assert!(status(ServiceControlAccept::STOP)
+            .validate_against(instance_type)
+            .is_ok());
+        assert!(matches!(
+            status(ServiceControlAccept::STOP | ServiceControlAccept::PRESHUTDOWN)
+                .validate_against(instance_type),
+            Err(Error::InvalidServiceStatus(_))
+        ));
+        assert!(status(
+            (ServiceControlAccept::STOP | ServiceControlAccept::PRESHUTDOWN).for_user_service()
+        )
+        .validate_against(instance_type)
+        .is_ok());
+    }
+
+    #[test]
+    fn test_service_trigger_from_raw_keeps_data_items() {
+        let mut data = *b"80\0";
+        let mut raw_item = Services::SERVICE_TRIGGER_SPECIFIC_DATA_ITEM {
+            dwDataType: Services::SERVICE_TRIGGER_DATA_TYPE_STRING,
+            cbData: data.len() as u32,
+            pData: data.as_mut_ptr(),
+        };
+        let raw_trigger = Services::SERVICE_TRIGGER {
+            dwTriggerType: Services::SERVICE_TRIGGER_TYPE_NETWORK_ENDPOINT,
+            dwAction: Services::SERVICE_TRIGGER_ACTION_SERVICE_START,
+            pTriggerSubtype: ptr::null_mut(),
+            cDataItems: 1,
+            pDataItems: &mut raw_item,
+        };
+
+        let trigger = unsafe { ServiceTrigger::from_raw(&raw_trigger) }.unwrap();
+        assert_eq!(
+            trigger.data_items,
+            vec![ServiceTriggerDataItem {
+                data_type: Services::SERVICE_TRIGGER_DATA_TYPE_STRING,
+                data: b"80\0".to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_config_cache_discards_stale_results() {
+        let config = ServiceConfig {
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from(r"C:\service.exe"),
+            load_order_group: None,
+            tag_id: 0,
+            dependencies: vec![],
+            account_name: None,
+            display_name: OsString::from("My service"),
+        };
+        let mut cache = ConfigCache::default();
+
+        let generation = cache.generation;
+        cache.invalidate();
+        cache.store_config(generation, config.clone());
+        assert!(cache.config.is_none());
+
+        cache.store_config(cache.generation, config.clone());
+        assert_eq!(cache.config, Some(config));
+        cache.invalidate();
+        assert!(cache.config.is_none());
+    }
+
+    #[test]
+    fn test_moved_away_from() {
+        use ServiceState::*;
+
+        assert!(moved_away_from(Running, Some(StartPending), Stopped));
+        assert!(moved_away_from(Running, None, Stopped));
+        assert!(!moved_away_from(Running, None, StartPending));
+
+        assert!(moved_away_from(Stopped, Some(StopPending), Running));
+        assert!(!moved_away_from(Stopped, None, Running));
+        assert!(!moved_away_from(Stopped, Some(Running), StopPending));
+    }
+
+    #[test]
+    fn test_optional_component() {
+        let os_error =
+            |code: u32| Err::<(), _>(Error::winapi(io::Error::from_raw_os_error(code as i32)));
+
+        assert_eq!(optional_component(Ok(5)).unwrap(), Some(5));
+        assert_eq!(
+            optional_component(os_error(ERROR_INVALID_LEVEL)).unwrap(),
+            None
+        );
+        assert_eq!(
+            optional_component(os_error(ERROR_NOT_SUPPORTED)).unwrap(),
+            None
+        );
+        assert_eq!(
+            optional_component(os_error(ERROR_FILE_NOT_FOUND)).unwrap(),
+            None
+        );
+        assert!(matches!(
+            optional_component(os_error(
+                windows_sys::Win32::Foundation::ERROR_ACCESS_DENIED
+            )),
+            Err(Error::AccessDenied(_))
+        ));
+        assert!(matches!(
+            optional_component(os_error(ERROR_INSUFFICIENT_BUFFER)),
+            Err(Error::Winapi(_))
+        ));
+        assert!(matches!(
+            optional_component::<()>(Err(Error::ParseValue(
+                "service launch protected",
+                ParseRawError::InvalidInteger(7)
+            ))),
+            Err(Error::ParseValue(..))
+        ));
+    }
 }
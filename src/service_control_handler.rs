@@ -1,49 +1,525 @@
+use std::any::Any;
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::io;
 use std::os::raw::c_void;
 use std::os::windows::io::{AsRawHandle, RawHandle};
-use widestring::WideCString;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+use widestring::{u16cstr, WideCString};
 use windows_sys::Win32::{
-    Foundation::{ERROR_CALL_NOT_IMPLEMENTED, NO_ERROR},
-    System::Services,
+    Foundation::{
+        ERROR_BUSY, ERROR_CALL_NOT_IMPLEMENTED, ERROR_FILE_NOT_FOUND, ERROR_PROCESS_ABORTED,
+        NO_ERROR,
+    },
+    System::{Diagnostics::Debug, Registry, Services},
+    UI::WindowsAndMessaging,
 };
 
-use crate::service::{ServiceControl, ServiceStatus};
+use crate::service::{
+    HardwareProfileChangeParam, ParseRawError, PowerEventParam, ServiceControl,
+    ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
+    SessionChangeParam,
+};
 use crate::{Error, Result};
 
 /// A struct that holds a unique token for updating the status of the corresponding service.
-#[derive(Debug, Clone, Copy)]
-pub struct ServiceStatusHandle(Services::SERVICE_STATUS_HANDLE);
+///
+/// The handle is cheap to clone and all clones refer to the same service.
+#[derive(Debug, Clone)]
+pub struct ServiceStatusHandle(Arc<StatusHandleInner>);
+
+/// The state shared between all clones of a [`ServiceStatusHandle`].
+#[derive(Debug)]
+struct StatusHandleInner {
+    handle: Services::SERVICE_STATUS_HANDLE,
+    /// The controls that the event handler declared to handle, see [`register_with_controls`].
+    declared_controls: Option<ServiceControlAccept>,
+}
 
 impl ServiceStatusHandle {
-    fn from_handle(handle: Services::SERVICE_STATUS_HANDLE) -> Self {
-        ServiceStatusHandle(handle)
+    fn from_handle(
+        handle: Services::SERVICE_STATUS_HANDLE,
+        declared_controls: Option<ServiceControlAccept>,
+    ) -> Self {
+        ServiceStatusHandle(Arc::new(StatusHandleInner {
+            handle,
+            declared_controls,
+        }))
     }
 
     /// Report the new service status to the system.
+    ///
+    /// In debug builds, a warning is written to the debugger output, which can be watched with
+    /// tools like DebugView, if the handle was obtained from [`register_with_controls`] and
+    /// [`ServiceStatus::controls_accepted`] contains controls that were not declared there. The
+    /// status is reported regardless.
     pub fn set_service_status(&self, service_status: ServiceStatus) -> crate::Result<()> {
+        if let Some(declared_controls) = self.0.declared_controls {
+            if cfg!(debug_assertions)
+                && !declared_controls.contains(service_status.controls_accepted)
+            {
+                debug_warning(&format!(
+                    "windows-service: reported controls {:?} that the event handler does not \
+                     handle, declared controls: {:?}",
+                    service_status.controls_accepted, declared_controls,
+                ));
+            }
+        }
         let raw_service_status = service_status.to_raw();
-        let result = unsafe { Services::SetServiceStatus(self.0, &raw_service_status) };
+        let result = unsafe { Services::SetServiceStatus(self.0.handle, &raw_service_status) };
         if result == 0 {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
             Ok(())
         }
     }
+
+    /// Register the service type bits with the server service, so that the service is reported
+    /// to the `NetServerEnum` function.
+    ///
+    /// This is only relevant for legacy network services participating in the server
+    /// announcement protocol.
+    ///
+    /// # Arguments
+    ///
+    /// * `service_bits` - The service type bits to set or clear. The bits reserved by Microsoft
+    ///   cannot be used.
+    /// * `set_bits_on` - Pass `true` to set the bits, or `false` to clear them.
+    /// * `update_immediately` - Pass `true` to announce the change to the network immediately,
+    ///   otherwise it is announced at the next periodic announcement.
+    ///
+    /// Please refer to MSDN for more info:\
+    /// <https://docs.microsoft.com/en-us/windows/win32/api/lmserver/nf-lmserver-setservicebits>
+    pub fn set_service_bits(
+        &self,
+        service_bits: u32,
+        set_bits_on: bool,
+        update_immediately: bool,
+    ) -> crate::Result<()> {
+        let result = unsafe {
+            Services::SetServiceBits(
+                self.0.handle,
+                service_bits,
+                set_bits_on as i32,
+                update_immediately as i32,
+            )
+        };
+        if result == 0 {
+            Err(Error::winapi(io::Error::last_os_error()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Start reporting [`ServiceState::StartPending`] periodically from a background thread.
+    ///
+    /// This is intended for services whose startup may take an unbounded amount of time, for
+    /// example while waiting for the network to come up. The status is reported immediately and
+    /// then once every `interval` with an incremented checkpoint, until the returned
+    /// [`KeepAlive`] is stopped or dropped. `interval` should be comfortably shorter than
+    /// `wait_hint`.
+    ///
+    /// The guard does not report any status when it stops, so the service should report
+    /// [`ServiceState::Running`] (or [`ServiceState::Stopped`] if the startup failed) right
+    /// after stopping it.
+    ///
+    /// # Arguments
+    ///
+    /// * `service_type` - The service type to report along with the pending state.
+    /// * `wait_hint` - The wait hint to report with every update.
+    /// * `interval` - How often to report progress.
+    pub fn keep_alive(
+        &self,
+        service_type: ServiceType,
+        wait_hint: Duration,
+        interval: Duration,
+    ) -> crate::Result<KeepAlive> {
+        let status = ServiceStatus {
+            service_type,
+            current_state: ServiceState::StartPending,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 1,
+            wait_hint,
+            process_id: None,
+        };
+        self.report_pending(status, interval, None)
+    }
+
+    /// Report a pending state, run `work` and then report the state that follows.
+    ///
+    /// This implements the checkpoint protocol for an operation with a known time budget. The
+    /// pending state is reported with a wait hint of `total`, and the checkpoint is incremented
+    /// from a background thread while `work` runs. Once `total` has elapsed the checkpoint is no
+    /// longer incremented, so that the system can detect a service that exceeds its budget.
+    /// When `work` returns, the settled state is reported with `controls_accepted`:
+    ///
+    /// * [`ServiceState::StartPending`] and [`ServiceState::ContinuePending`] settle in
+    ///   [`ServiceState::Running`].
+    /// * [`ServiceState::PausePending`] settles in [`ServiceState::Paused`].
+    /// * [`ServiceState::StopPending`] settles in [`ServiceState::Stopped`]. No controls are
+    ///   accepted in this state.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidServiceStatus`] if `pending_state` is not a pending state, or the
+    /// error that occurred when reporting the status.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use windows_service::service::{ServiceControlAccept, ServiceState, ServiceType};
+    /// use windows_service::service_control_handler::ServiceStatusHandle;
+    ///
+    /// fn stop_service(status_handle: &ServiceStatusHandle) -> windows_service::Result<()> {
+    ///     status_handle.run_with_budget(
+    ///         ServiceType::OWN_PROCESS,
+    ///         ServiceState::StopPending,
+    ///         ServiceControlAccept::empty(),
+    ///         Duration::from_secs(20),
+    ///         || {
+    ///             // Flush state to disk, close connections...
+    ///         },
+    ///     )
+    /// }
+    /// ```
+    pub fn run_with_budget<T>(
+        &self,
+        service_type: ServiceType,
+        pending_state: ServiceState,
+        controls_accepted: ServiceControlAccept,
+        total: Duration,
+        work: impl FnOnce() -> T,
+    ) -> crate::Result<T> {
+        let (settled_state, controls_accepted) = match pending_state {
+            ServiceState::StartPending | ServiceState::ContinuePending => {
+                (ServiceState::Running, controls_accepted)
+            }
+            ServiceState::PausePending => (ServiceState::Paused, controls_accepted),
+            ServiceState::StopPending => (ServiceState::Stopped, ServiceControlAccept::empty()),
+            _ => {
+                return Err(Error::InvalidServiceStatus(
+                    "run_with_budget requires a pending state",
+                ))
+            }
+        };
+
+        let pending_status = ServiceStatus {
+            service_type,
+            current_state: pending_state,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 1,
+            wait_hint: total,
+            process_id: None,
+        };
+        let interval = (total / CHECKPOINTS_PER_BUDGET).max(MIN_CHECKPOINT_INTERVAL);
+        let reporter =
+            self.report_pending(pending_status, interval, Some(Instant::now() + total))?;
+        let result = work();
+        reporter.stop()?;
+
+        self.set_service_status(ServiceStatus {
+            service_type,
+            current_state: settled_state,
+            controls_accepted,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })?;
+        Ok(result)
+    }
+
+    /// Report [`ServiceState::Stopped`] with the given exit code.
+    ///
+    /// No controls are accepted in the stopped state. Once a service has reported that it is
+    /// stopped it should not report any other state and the process should exit shortly after,
+    /// since the system may decide to launch the service again. Final cleanup is fine as long as
+    /// it does not depend on receiving further controls.
+    ///
+    /// See [`ServiceStatusHandle::stop_guard`] for making sure that the report is sent on all
+    /// exit paths.
+    pub fn report_stopped(
+        &self,
+        service_type: ServiceType,
+        exit_code: ServiceExitCode,
+    ) -> crate::Result<()> {
+        self.set_service_status(ServiceStatus {
+            service_type,
+            current_state: ServiceState::Stopped,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })
+    }
+
+    /// Report [`ServiceState::PausePending`] while the service is pausing.
+    ///
+    /// No controls are accepted while pausing. Report again with an incremented `checkpoint`
+    /// for lengthy operations, and [`ServiceStatusHandle::report_paused`] once paused.
+    pub fn report_pause_pending(
+        &self,
+        service_type: ServiceType,
+        checkpoint: u32,
+        wait_hint: Duration,
+    ) -> crate::Result<()> {
+        self.report_pause_lifecycle(
+            service_type,
+            ServiceState::PausePending,
+            ServiceControlAccept::empty(),
+            checkpoint,
+            wait_hint,
+        )
+    }
+
+    /// Report [`ServiceState::Paused`].
+    ///
+    /// [`ServiceControlAccept::PAUSE_CONTINUE`] is always accepted in addition to
+    /// `controls_accepted`, since the service could not be continued otherwise.
+    pub fn report_paused(
+        &self,
+        service_type: ServiceType,
+        controls_accepted: ServiceControlAccept,
+    ) -> crate::Result<()> {
+        self.report_pause_lifecycle(
+            service_type,
+            ServiceState::Paused,
+            controls_accepted | ServiceControlAccept::PAUSE_CONTINUE,
+            0,
+            Duration::default(),
+        )
+    }
+
+    /// Report [`ServiceState::ContinuePending`] while the service is resuming from a pause.
+    ///
+    /// No controls are accepted while resuming. Report again with an incremented `checkpoint`
+    /// for lengthy operations, and [`ServiceStatusHandle::report_running_from_pause`] once
+    /// running.
+    pub fn report_continue_pending(
+        &self,
+        service_type: ServiceType,
+        checkpoint: u32,
+        wait_hint: Duration,
+    ) -> crate::Result<()> {
+        self.report_pause_lifecycle(
+            service_type,
+            ServiceState::ContinuePending,
+            ServiceControlAccept::empty(),
+            checkpoint,
+            wait_hint,
+        )
+    }
+
+    /// Report [`ServiceState::Running`] after the service has been continued.
+    ///
+    /// [`ServiceControlAccept::PAUSE_CONTINUE`] is always accepted in addition to
+    /// `controls_accepted`, so that the service can be paused again.
+    pub fn report_running_from_pause(
+        &self,
+        service_type: ServiceType,
+        controls_accepted: ServiceControlAccept,
+    ) -> crate::Result<()> {
+        self.report_pause_lifecycle(
+            service_type,
+            ServiceState::Running,
+            controls_accepted | ServiceControlAccept::PAUSE_CONTINUE,
+            0,
+            Duration::default(),
+        )
+    }
+
+    /// Private helper that reports a state of the pause and continue lifecycle.
+    fn report_pause_lifecycle(
+        &self,
+        service_type: ServiceType,
+        current_state: ServiceState,
+        controls_accepted: ServiceControlAccept,
+        checkpoint: u32,
+        wait_hint: Duration,
+    ) -> crate::Result<()> {
+        self.set_service_status(ServiceStatus {
+            service_type,
+            current_state,
+            controls_accepted,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint,
+            wait_hint,
+            process_id: None,
+        })
+    }
+
+    /// Create a guard that reports [`ServiceState::Stopped`] exactly once when it is dropped.
+    ///
+    /// The guard reports [`ServiceExitCode::NO_ERROR`] unless another exit code is set with
+    /// [`StopGuard::set_exit_code`]. If the guard is dropped while the thread is panicking and
+    /// the exit code indicates success, `ERROR_PROCESS_ABORTED` is reported instead, so that the
+    /// system and the failure actions see that the service did not stop cleanly.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::{ServiceExitCode, ServiceType};
+    /// use windows_service::service_control_handler::ServiceStatusHandle;
+    ///
+    /// fn run_service(status_handle: &ServiceStatusHandle) -> windows_service::Result<()> {
+    ///     let mut stop_guard = status_handle.stop_guard(ServiceType::OWN_PROCESS);
+    ///     // Run the service. The stopped state is reported even if this panics.
+    ///     if let Err(code) = do_work() {
+    ///         stop_guard.set_exit_code(ServiceExitCode::ServiceSpecific(code));
+    ///     }
+    ///     stop_guard.report()
+    /// }
+    /// # fn do_work() -> Result<(), u32> { Ok(()) }
+    /// ```
+    pub fn stop_guard(&self, service_type: ServiceType) -> StopGuard {
+        StopGuard {
+            status_handle: self.clone(),
+            service_type,
+            exit_code: ServiceExitCode::NO_ERROR,
+            reported: false,
+        }
+    }
+
+    /// Private helper that reports `status` and then increments the checkpoint once every
+    /// `interval` from a background thread, until the returned guard is stopped or `deadline`
+    /// passes.
+    fn report_pending(
+        &self,
+        mut status: ServiceStatus,
+        interval: Duration,
+        deadline: Option<Instant>,
+    ) -> crate::Result<KeepAlive> {
+        self.set_service_status(status.clone())?;
+
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let status_handle = self.clone();
+        let thread = thread::spawn(move || loop {
+            match stop_rx.recv_timeout(interval) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                        return Ok(());
+                    }
+                    status.checkpoint = status.checkpoint.wrapping_add(1).max(1);
+                    status_handle.set_service_status(status.clone())?;
+                }
+                // Either stopped explicitly or the guard was dropped.
+                Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        });
+
+        Ok(KeepAlive {
+            stop_tx: Some(stop_tx),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// How many times the checkpoint is incremented over the budget in
+/// [`ServiceStatusHandle::run_with_budget`].
+const CHECKPOINTS_PER_BUDGET: u32 = 10;
+
+/// The shortest interval between checkpoint increments in
+/// [`ServiceStatusHandle::run_with_budget`].
+const MIN_CHECKPOINT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A guard that keeps reporting [`ServiceState::StartPending`] until it is stopped or dropped.
+///
+/// See [`ServiceStatusHandle::keep_alive`].
+#[derive(Debug)]
+pub struct KeepAlive {
+    stop_tx: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<crate::Result<()>>>,
+}
+
+impl KeepAlive {
+    /// Stop reporting progress and wait for the background thread to exit.
+    ///
+    /// Returns the error that made the background thread give up early, if any.
+    pub fn stop(mut self) -> crate::Result<()> {
+        match self.stop_and_join() {
+            Some(Ok(result)) => result,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Ok(()),
+        }
+    }
+
+    fn stop_and_join(&mut self) -> Option<thread::Result<crate::Result<()>>> {
+        // Dropping the sender wakes up the background thread.
+        self.stop_tx.take();
+        self.thread.take().map(|thread| thread.join())
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        let _ = self.stop_and_join();
+    }
+}
+
+/// A guard that reports [`ServiceState::Stopped`] exactly once, when it is dropped or
+/// explicitly reported.
+///
+/// See [`ServiceStatusHandle::stop_guard`].
+#[derive(Debug)]
+pub struct StopGuard {
+    status_handle: ServiceStatusHandle,
+    service_type: ServiceType,
+    exit_code: ServiceExitCode,
+    reported: bool,
+}
+
+impl StopGuard {
+    /// Set the exit code to report.
+    pub fn set_exit_code(&mut self, exit_code: ServiceExitCode) {
+        self.exit_code = exit_code;
+    }
+
+    /// Report the stopped state now instead of when the guard is dropped.
+    ///
+    /// Unlike dropping the guard, this returns the error that occurred when reporting the status.
+    pub fn report(mut self) -> crate::Result<()> {
+        self.reported = true;
+        self.status_handle
+            .report_stopped(self.service_type, self.exit_code)
+    }
+}
+
+impl Drop for StopGuard {
+    fn drop(&mut self) {
+        if self.reported {
+            return;
+        }
+        self.reported = true;
+        let exit_code = if thread::panicking() && self.exit_code.is_success() {
+            ServiceExitCode::Win32(ERROR_PROCESS_ABORTED)
+        } else {
+            self.exit_code
+        };
+        let _ = self
+            .status_handle
+            .report_stopped(self.service_type, exit_code);
+    }
 }
 
 impl AsRawHandle for ServiceStatusHandle {
     /// Get access to the raw handle to use in other Windows APIs
     fn as_raw_handle(&self) -> RawHandle {
-        self.0 as _
+        self.0.handle as _
     }
 }
 
 // Underlying SERVICE_STATUS_HANDLE is thread safe.
 // See remarks section for more info:
 // https://msdn.microsoft.com/en-us/library/windows/desktop/ms686241(v=vs.85).aspx
-unsafe impl Send for ServiceStatusHandle {}
-unsafe impl Sync for ServiceStatusHandle {}
+unsafe impl Send for StatusHandleInner {}
+unsafe impl Sync for StatusHandleInner {}
 
 /// Abstraction over the return value of service control handler.
 /// The meaning of each of variants in this enum depends on the type of received event.
@@ -56,27 +532,336 @@ pub enum ServiceControlHandlerResult {
     /// Either used to acknowledge the call or grant the permission in advanced events.
     NoError,
     /// The received event is not implemented.
+    ///
+    /// Note that for events that ask for permission, such as
+    /// [`PowerEventParam::QuerySuspend`] or [`HardwareProfileChangeParam::QueryChangeConfig`],
+    /// any value other than [`ServiceControlHandlerResult::NoError`] denies the request. Use
+    /// [`grant_queries_by_default`] to grant such requests unless the handler explicitly denies
+    /// them.
+    ///
+    /// [`PowerEventParam::QuerySuspend`]: crate::service::PowerEventParam::QuerySuspend
+    /// [`HardwareProfileChangeParam::QueryChangeConfig`]: crate::service::HardwareProfileChangeParam::QueryChangeConfig
     NotImplemented,
     /// This variant is used to deny permission and return the reason error code in advanced
     /// events.
     Other(u32),
+    /// The event was passed on to another component, for instance a child service proxied by
+    /// this service.
+    ///
+    /// This is reported to the system the same way as [`ServiceControlHandlerResult::NoError`],
+    /// including granting the permission in advanced events, but lets handlers and their logging
+    /// tell forwarded events apart from handled ones.
+    Forwarded,
 }
 
 impl ServiceControlHandlerResult {
+    /// Deny a permission request, such as [`PowerEventParam::QuerySuspend`], without giving a
+    /// more specific reason.
+    ///
+    /// [`PowerEventParam::QuerySuspend`]: crate::service::PowerEventParam::QuerySuspend
+    pub const BROADCAST_QUERY_DENY: Self =
+        ServiceControlHandlerResult::Other(WindowsAndMessaging::BROADCAST_QUERY_DENY);
+
+    /// Deny a request because the service is busy, for instance in the middle of an operation
+    /// that cannot be interrupted.
+    pub const ERROR_BUSY: Self = ServiceControlHandlerResult::Other(ERROR_BUSY);
+
+    /// Create a result from a win32 error code.
+    ///
+    /// `NO_ERROR` and `ERROR_CALL_NOT_IMPLEMENTED` map to [`ServiceControlHandlerResult::NoError`]
+    /// and [`ServiceControlHandlerResult::NotImplemented`] respectively, any other code is
+    /// returned as [`ServiceControlHandlerResult::Other`].
+    pub fn from_win32_error(code: u32) -> Self {
+        match code {
+            NO_ERROR => ServiceControlHandlerResult::NoError,
+            ERROR_CALL_NOT_IMPLEMENTED => ServiceControlHandlerResult::NotImplemented,
+            _ => ServiceControlHandlerResult::Other(code),
+        }
+    }
+
     pub fn to_raw(&self) -> u32 {
         match *self {
-            ServiceControlHandlerResult::NoError => NO_ERROR,
+            ServiceControlHandlerResult::NoError | ServiceControlHandlerResult::Forwarded => {
+                NO_ERROR
+            }
             ServiceControlHandlerResult::NotImplemented => ERROR_CALL_NOT_IMPLEMENTED,
             ServiceControlHandlerResult::Other(code) => code,
         }
     }
 }
 
+/// Wrap a service event handler so that permission requests it does not implement are granted.
+///
+/// Some events ask the service for permission, for instance
+/// [`PowerEventParam::QuerySuspend`] asks whether the system may suspend and
+/// [`HardwareProfileChangeParam::QueryChangeConfig`] asks whether the hardware profile may
+/// change. Returning [`ServiceControlHandlerResult::NotImplemented`] for those denies the
+/// request, so a handler that simply does not care about them can block the system by accident.
+/// The returned handler replaces `NotImplemented` with [`ServiceControlHandlerResult::NoError`]
+/// for these events, while explicit denials with [`ServiceControlHandlerResult::Other`] are
+/// passed through unchanged.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use windows_service::service::ServiceControl;
+/// use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+///
+/// # fn main() -> windows_service::Result<()> {
+/// let event_handler = service_control_handler::grant_queries_by_default(|control_event| {
+///     match control_event {
+///         ServiceControl::Stop => ServiceControlHandlerResult::NoError,
+///         _ => ServiceControlHandlerResult::NotImplemented,
+///     }
+/// });
+/// let status_handle = service_control_handler::register("my_service_name", event_handler)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`PowerEventParam::QuerySuspend`]: crate::service::PowerEventParam::QuerySuspend
+/// [`HardwareProfileChangeParam::QueryChangeConfig`]: crate::service::HardwareProfileChangeParam::QueryChangeConfig
+pub fn grant_queries_by_default<F>(
+    mut event_handler: F,
+) -> impl FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send
+where
+    F: FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send,
+{
+    move |control_event| {
+        let is_query = is_permission_query(&control_event);
+        match event_handler(control_event) {
+            ServiceControlHandlerResult::NotImplemented if is_query => {
+                ServiceControlHandlerResult::NoError
+            }
+            result => result,
+        }
+    }
+}
+
+/// Returns `true` for events where the return value grants or denies a request.
+fn is_permission_query(control_event: &ServiceControl) -> bool {
+    matches!(
+        control_event,
+        ServiceControl::PowerEvent(PowerEventParam::QuerySuspend)
+            | ServiceControl::HardwareProfileChange(HardwareProfileChangeParam::QueryChangeConfig)
+    )
+}
+
+/// A notification handler registered with [`ControlDispatcher`].
+type NotificationHandler = Box<dyn FnMut() + Send + 'static>;
+
+/// A builder for a service event handler that dispatches each control to its own closure.
+///
+/// Controls that do not have a closure registered are answered with
+/// [`ServiceControlHandlerResult::NotImplemented`], except for [`ServiceControl::Interrogate`]
+/// which is always acknowledged. The controls to accept are derived from the registered
+/// closures, see [`ControlDispatcher::controls_accepted`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::sync::mpsc;
+/// use windows_service::service_control_handler::{ControlDispatcher, ServiceControlHandlerResult};
+///
+/// # fn main() -> windows_service::Result<()> {
+/// let (shutdown_tx, shutdown_rx) = mpsc::channel();
+/// let dispatcher = ControlDispatcher::new()
+///     .on_stop(move || {
+///         let _ = shutdown_tx.send(());
+///     })
+///     .on_session_change(|param| println!("session changed: {:?}", param.reason))
+///     .on_power_event(|_event| ServiceControlHandlerResult::NoError);
+///
+/// let controls_accepted = dispatcher.controls_accepted();
+/// let status_handle = dispatcher.register("my_service_name")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ControlDispatcher {
+    on_stop: Option<NotificationHandler>,
+    on_shutdown: Option<NotificationHandler>,
+    on_preshutdown: Option<NotificationHandler>,
+    on_pause: Option<NotificationHandler>,
+    on_continue: Option<NotificationHandler>,
+    on_param_change: Option<NotificationHandler>,
+    on_time_change: Option<NotificationHandler>,
+    on_session_change: Option<Box<dyn FnMut(SessionChangeParam) + Send + 'static>>,
+    on_power_event:
+        Option<Box<dyn FnMut(PowerEventParam) -> ServiceControlHandlerResult + Send + 'static>>,
+}
+
+impl ControlDispatcher {
+    /// Create a dispatcher without any closures registered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle [`ServiceControl::Stop`].
+    pub fn on_stop(mut self, handler: impl FnMut() + Send + 'static) -> Self {
+        self.on_stop = Some(Box::new(handler));
+        self
+    }
+
+    /// Handle [`ServiceControl::Shutdown`]. A service cannot accept both shutdown and
+    /// preshutdown, so this closure is not used when [`ControlDispatcher::on_preshutdown`] is
+    /// also registered.
+    pub fn on_shutdown(mut self, handler: impl FnMut() + Send + 'static) -> Self {
+        self.on_shutdown = Some(Box::new(handler));
+        self
+    }
+
+    /// Handle [`ServiceControl::Preshutdown`].
+    pub fn on_preshutdown(mut self, handler: impl FnMut() + Send + 'static) -> Self {
+        self.on_preshutdown = Some(Box::new(handler));
+        self
+    }
+
+    /// Handle [`ServiceControl::Pause`]. The service only accepts pause and continue when both
+    /// [`ControlDispatcher::on_pause`] and [`ControlDispatcher::on_continue`] are registered.
+    pub fn on_pause(mut self, handler: impl FnMut() + Send + 'static) -> Self {
+        self.on_pause = Some(Box::new(handler));
+        self
+    }
+
+    /// Handle [`ServiceControl::Continue`]. The service only accepts pause and continue when
+    /// both [`ControlDispatcher::on_pause`] and [`ControlDispatcher::on_continue`] are
+    /// registered.
+    pub fn on_continue(mut self, handler: impl FnMut() + Send + 'static) -> Self {
+        self.on_continue = Some(Box::new(handler));
+        self
+    }
+
+    /// Handle [`ServiceControl::ParamChange`].
+    pub fn on_param_change(mut self, handler: impl FnMut() + Send + 'static) -> Self {
+        self.on_param_change = Some(Box::new(handler));
+        self
+    }
+
+    /// Handle [`ServiceControl::TimeChange`].
+    pub fn on_time_change(mut self, handler: impl FnMut() + Send + 'static) -> Self {
+        self.on_time_change = Some(Box::new(handler));
+        self
+    }
+
+    /// Handle [`ServiceControl::SessionChange`].
+    pub fn on_session_change(
+        mut self,
+        handler: impl FnMut(SessionChangeParam) + Send + 'static,
+    ) -> Self {
+        self.on_session_change = Some(Box::new(handler));
+        self
+    }
+
+    /// Handle [`ServiceControl::PowerEvent`].
+    ///
+    /// The closure decides the result since some power events, such as
+    /// [`PowerEventParam::QuerySuspend`], ask for permission.
+    pub fn on_power_event(
+        mut self,
+        handler: impl FnMut(PowerEventParam) -> ServiceControlHandlerResult + Send + 'static,
+    ) -> Self {
+        self.on_power_event = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns the controls that the service should accept given the registered closures.
+    ///
+    /// If closures are registered for both shutdown and preshutdown, only preshutdown is
+    /// accepted, see [`ServiceControlAccept::prefer_preshutdown`].
+    pub fn controls_accepted(&self) -> ServiceControlAccept {
+        let mut controls = ServiceControlAccept::empty();
+        controls.set(ServiceControlAccept::STOP, self.on_stop.is_some());
+        controls.set(ServiceControlAccept::SHUTDOWN, self.on_shutdown.is_some());
+        controls.set(
+            ServiceControlAccept::PRESHUTDOWN,
+            self.on_preshutdown.is_some(),
+        );
+        controls.set(
+            ServiceControlAccept::PAUSE_CONTINUE,
+            self.on_pause.is_some() && self.on_continue.is_some(),
+        );
+        controls.set(
+            ServiceControlAccept::PARAM_CHANGE,
+            self.on_param_change.is_some(),
+        );
+        controls.set(
+            ServiceControlAccept::TIME_CHANGE,
+            self.on_time_change.is_some(),
+        );
+        controls.set(
+            ServiceControlAccept::SESSION_CHANGE,
+            self.on_session_change.is_some(),
+        );
+        controls.set(
+            ServiceControlAccept::POWER_EVENT,
+            self.on_power_event.is_some(),
+        );
+        controls.prefer_preshutdown()
+    }
+
+    /// Dispatch a single control to the registered closure.
+    pub fn dispatch(&mut self, control_event: ServiceControl) -> ServiceControlHandlerResult {
+        fn notify(handler: &mut Option<NotificationHandler>) -> ServiceControlHandlerResult {
+            match handler {
+                Some(handler) => {
+                    handler();
+                    ServiceControlHandlerResult::NoError
+                }
+                None => ServiceControlHandlerResult::NotImplemented,
+            }
+        }
+
+        match control_event {
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            ServiceControl::Stop => notify(&mut self.on_stop),
+            ServiceControl::Shutdown => notify(&mut self.on_shutdown),
+            ServiceControl::Preshutdown => notify(&mut self.on_preshutdown),
+            ServiceControl::Pause => notify(&mut self.on_pause),
+            ServiceControl::Continue => notify(&mut self.on_continue),
+            ServiceControl::ParamChange => notify(&mut self.on_param_change),
+            ServiceControl::TimeChange => notify(&mut self.on_time_change),
+            ServiceControl::SessionChange(param) => match &mut self.on_session_change {
+                Some(handler) => {
+                    handler(param);
+                    ServiceControlHandlerResult::NoError
+                }
+                None => ServiceControlHandlerResult::NotImplemented,
+            },
+            ServiceControl::PowerEvent(param) => match &mut self.on_power_event {
+                Some(handler) => handler(param),
+                None => ServiceControlHandlerResult::NotImplemented,
+            },
+            _ => ServiceControlHandlerResult::NotImplemented,
+        }
+    }
+
+    /// Convert the dispatcher into an event handler that can be passed to [`register`].
+    pub fn into_handler(
+        mut self,
+    ) -> impl FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send {
+        move |control_event| self.dispatch(control_event)
+    }
+
+    /// Register the dispatcher for receiving service events.
+    ///
+    /// This is a shorthand for calling [`register_with_controls`] with
+    /// [`ControlDispatcher::controls_accepted`].
+    pub fn register(self, service_name: impl AsRef<OsStr>) -> Result<ServiceStatusHandle> {
+        let controls = self.controls_accepted();
+        register_with_controls(service_name, controls, self.into_handler())
+    }
+}
+
 /// Register a closure for receiving service events.
 ///
 /// Returns [`ServiceStatusHandle`] that can be used to report the service status back to the
 /// system.
 ///
+/// The system requires every service to acknowledge [`ServiceControl::Interrogate`]. If the
+/// closure returns [`ServiceControlHandlerResult::NotImplemented`] for it, the control is
+/// acknowledged with `NO_ERROR` instead.
+///
 /// # Example
 ///
 /// ```rust,no_run
@@ -104,6 +889,54 @@ impl ServiceControlHandlerResult {
 /// # fn main() {}
 /// ```
 pub fn register<F>(service_name: impl AsRef<OsStr>, event_handler: F) -> Result<ServiceStatusHandle>
+where
+    F: FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send,
+{
+    register_impl(service_name, event_handler, None)
+}
+
+/// Register a closure for receiving service events, declaring the controls it handles.
+///
+/// This behaves like [`register`], but also records `controls` on the returned
+/// [`ServiceStatusHandle`]. In debug builds, reporting a status that accepts controls outside of
+/// this set writes a warning to the debugger output, which catches a handler that does not
+/// handle the controls the service claims to accept. Release builds do not perform any check.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use windows_service::service::{ServiceControl, ServiceControlAccept};
+/// use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+///
+/// # fn main() -> windows_service::Result<()> {
+/// let status_handle = service_control_handler::register_with_controls(
+///     "my_service_name",
+///     ServiceControlAccept::STOP,
+///     |control_event| match control_event {
+///         ServiceControl::Stop => ServiceControlHandlerResult::NoError,
+///         ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+///         _ => ServiceControlHandlerResult::NotImplemented,
+///     },
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn register_with_controls<F>(
+    service_name: impl AsRef<OsStr>,
+    controls: ServiceControlAccept,
+    event_handler: F,
+) -> Result<ServiceStatusHandle>
+where
+    F: FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send,
+{
+    register_impl(service_name, event_handler, Some(controls))
+}
+
+fn register_impl<F>(
+    service_name: impl AsRef<OsStr>,
+    event_handler: F,
+    declared_controls: Option<ServiceControlAccept>,
+) -> Result<ServiceStatusHandle>
 where
     F: FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send,
 {
@@ -126,12 +959,178 @@ where
     if status_handle.is_null() {
         // Release the `event_handler` in case of an error.
         let _: Box<F> = unsafe { Box::from_raw(context) };
-        Err(Error::Winapi(io::Error::last_os_error()))
+        Err(Error::winapi(io::Error::last_os_error()))
+    } else {
+        Ok(ServiceStatusHandle::from_handle(
+            status_handle,
+            declared_controls,
+        ))
+    }
+}
+
+/// Register a closure for receiving service events and return a guard that unregisters it.
+///
+/// This behaves like [`register`], except that the closure is not released when the service
+/// receives [`ServiceControl::Stop`], [`ServiceControl::Shutdown`] or
+/// [`ServiceControl::Preshutdown`]. Instead it is released when the returned [`HandlerGuard`] is
+/// dropped, which makes it possible to register and unregister handlers repeatedly within one
+/// process, for example in a test harness hosting a fake service.
+///
+/// Production services rarely need this, since the handler lives until the end of the service
+/// lifecycle anyway.
+pub fn register_guarded<F>(
+    service_name: impl AsRef<OsStr>,
+    event_handler: F,
+) -> Result<(ServiceStatusHandle, HandlerGuard)>
+where
+    F: FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send,
+{
+    let service_name = WideCString::from_os_str(service_name)
+        .map_err(|_| Error::ArgumentHasNulByte("service name"))?;
+
+    let (id, handler) = insert_guarded_handler(event_handler);
+    let status_handle = unsafe {
+        Services::RegisterServiceCtrlHandlerExW(service_name.as_ptr(), handler, id as *mut c_void)
+    };
+
+    if status_handle.is_null() {
+        let error = io::Error::last_os_error();
+        // Release the `event_handler` in case of an error.
+        remove_guarded_handler(id);
+        Err(Error::winapi(error))
     } else {
-        Ok(ServiceStatusHandle::from_handle(status_handle))
+        let guard = HandlerGuard { service_name, id };
+        Ok((ServiceStatusHandle::from_handle(status_handle, None), guard))
+    }
+}
+
+/// Write a warning to the debugger output.
+fn debug_warning(message: &str) {
+    let message = WideCString::from_str_truncate(message);
+    unsafe { Debug::OutputDebugStringW(message.as_ptr()) };
+}
+
+/// A guard that unregisters an event handler when it is dropped.
+///
+/// The system offers no way to unregister a handler, so on drop the guard replaces it with one
+/// that only acknowledges [`ServiceControl::Interrogate`] and releases the closure. A control
+/// that is being handled while the guard is dropped runs to completion, the closure is released
+/// when it returns. The guard may also be dropped from within the closure.
+///
+/// See [`register_guarded`].
+#[derive(Debug)]
+pub struct HandlerGuard {
+    service_name: WideCString,
+    id: usize,
+}
+
+impl Drop for HandlerGuard {
+    fn drop(&mut self) {
+        // If this fails, the system may still call the old handler, which then finds no closure
+        // for the id and behaves like `interrogate_only_handler`.
+        unsafe {
+            Services::RegisterServiceCtrlHandlerExW(
+                self.service_name.as_ptr(),
+                Some(interrogate_only_handler),
+                std::ptr::null_mut(),
+            )
+        };
+        remove_guarded_handler(self.id);
     }
 }
 
+/// A closure registered with [`register_guarded`], a `Mutex<F>` behind the `Any`.
+type GuardedHandler = Arc<dyn Any + Send + Sync>;
+
+/// The closures registered with [`register_guarded`], by the id passed to the system as context.
+///
+/// The system only receives the id, so a control that arrives after the guard was dropped
+/// cannot reach a released closure.
+fn guarded_handlers() -> &'static Mutex<HashMap<usize, GuardedHandler>> {
+    static INIT: Once = Once::new();
+    static HANDLERS: AtomicPtr<Mutex<HashMap<usize, GuardedHandler>>> =
+        AtomicPtr::new(std::ptr::null_mut());
+    INIT.call_once(|| {
+        let handlers = Box::new(Mutex::new(HashMap::new()));
+        HANDLERS.store(Box::into_raw(handlers), Ordering::Release);
+    });
+    // The map is initialized above and never released.
+    unsafe { &*HANDLERS.load(Ordering::Acquire) }
+}
+
+/// Remove a closure from the registry of guarded closures.
+///
+/// The closure is released once no control that is being handled refers to it anymore.
+fn remove_guarded_handler(id: usize) {
+    let handler = guarded_handlers()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .remove(&id);
+    // Release the closure outside the registry lock, since its destructor may use the registry.
+    drop(handler);
+}
+
+/// Add `event_handler` to the registry of guarded closures.
+///
+/// Returns the id of the closure and the static handler to pass to the system along with it.
+fn insert_guarded_handler<F>(event_handler: F) -> (usize, Services::LPHANDLER_FUNCTION_EX)
+where
+    F: FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send,
+{
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let handler: GuardedHandler = Arc::new(Mutex::new(event_handler));
+    guarded_handlers()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(id, handler);
+    (id, Some(guarded_service_control_handler::<F>))
+}
+
+/// The time services get to stop at system shutdown when `WaitToKillServiceTimeout` is not set.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns how long the system waits for services to stop at system shutdown.
+///
+/// Services that accept [`ServiceControl::Shutdown`] can use this to prioritize their cleanup,
+/// since the system may terminate them once the timeout elapses. The value is read from
+/// `WaitToKillServiceTimeout` under `HKLM\SYSTEM\CurrentControlSet\Control`. If the value is
+/// not set, the system default of 5 seconds is returned.
+///
+/// This does not apply to services accepting [`ServiceControl::Preshutdown`], which are governed
+/// by their own preshutdown timeout.
+pub fn shutdown_timeout() -> Result<Duration> {
+    // Large enough for any number of milliseconds that fits in a u32.
+    let mut buffer = [0u16; 32];
+    let mut buffer_size = (buffer.len() * std::mem::size_of::<u16>()) as u32;
+    let status = unsafe {
+        Registry::RegGetValueW(
+            Registry::HKEY_LOCAL_MACHINE,
+            u16cstr!(r"SYSTEM\CurrentControlSet\Control").as_ptr(),
+            u16cstr!("WaitToKillServiceTimeout").as_ptr(),
+            Registry::RRF_RT_REG_SZ,
+            std::ptr::null_mut(),
+            buffer.as_mut_ptr() as *mut c_void,
+            &mut buffer_size,
+        )
+    };
+    if status == ERROR_FILE_NOT_FOUND {
+        return Ok(DEFAULT_SHUTDOWN_TIMEOUT);
+    }
+    if status != NO_ERROR {
+        return Err(Error::winapi(io::Error::from_raw_os_error(status as i32)));
+    }
+
+    let value = widestring::U16CStr::from_slice_truncate(&buffer)
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    value
+        .trim()
+        .parse::<u64>()
+        .map(Duration::from_millis)
+        .map_err(|_| Error::ParseValue("shutdown timeout", ParseRawError::InvalidString(value)))
+}
+
 /// Static service control handler
 #[allow(dead_code)]
 extern "system" fn service_control_handler<F>(
@@ -153,7 +1152,7 @@ where
                 ServiceControl::Stop | ServiceControl::Shutdown | ServiceControl::Preshutdown,
             );
 
-            let return_code = event_handler(service_control).to_raw();
+            let return_code = handle_control(event_handler, service_control);
 
             // Important: release context upon Stop, Shutdown or Preshutdown at the end of the
             // service lifecycle.
@@ -164,7 +1163,240 @@ where
             return_code
         }
 
-        // Report all unknown control commands as unimplemented
+        // Report controls with invalid parameters as unimplemented
+        Err(_) => ServiceControlHandlerResult::NotImplemented.to_raw(),
+    }
+}
+
+/// Static service control handler for [`register_guarded`], which never releases the context.
+extern "system" fn guarded_service_control_handler<F>(
+    control: u32,
+    event_type: u32,
+    event_data: *mut c_void,
+    context: *mut c_void,
+) -> u32
+where
+    F: FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send,
+{
+    // Hold on to the closure while handling the control, so that it outlives a concurrent drop
+    // of the `HandlerGuard`. The registry is not locked while the closure runs.
+    let event_handler = guarded_handlers()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(&(context as usize))
+        .cloned()
+        .and_then(|handler| handler.downcast::<Mutex<F>>().ok());
+    let event_handler = match event_handler {
+        Some(event_handler) => event_handler,
+        None => return interrogate_only_handler(control, event_type, event_data, context),
+    };
+
+    match unsafe { ServiceControl::from_raw(control, event_type, event_data) } {
+        Ok(service_control) => {
+            let mut event_handler = event_handler
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            handle_control(&mut *event_handler, service_control)
+        }
         Err(_) => ServiceControlHandlerResult::NotImplemented.to_raw(),
     }
 }
+
+/// Static service control handler installed when a [`HandlerGuard`] is dropped.
+extern "system" fn interrogate_only_handler(
+    control: u32,
+    _event_type: u32,
+    _event_data: *mut c_void,
+    _context: *mut c_void,
+) -> u32 {
+    if control == Services::SERVICE_CONTROL_INTERROGATE {
+        NO_ERROR
+    } else {
+        ServiceControlHandlerResult::NotImplemented.to_raw()
+    }
+}
+
+fn handle_control<F>(event_handler: &mut F, service_control: ServiceControl) -> u32
+where
+    F: FnMut(ServiceControl) -> ServiceControlHandlerResult,
+{
+    let is_interrogate = service_control == ServiceControl::Interrogate;
+    match event_handler(service_control) {
+        // All services must acknowledge Interrogate, even if they do not handle it.
+        ServiceControlHandlerResult::NotImplemented if is_interrogate => NO_ERROR,
+        result => result.to_raw(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grant_queries_by_default() {
+        let mut event_handler = grant_queries_by_default(|control_event| match control_event {
+            ServiceControl::HardwareProfileChange(_) => ServiceControlHandlerResult::Other(5),
+            _ => ServiceControlHandlerResult::NotImplemented,
+        });
+
+        assert!(matches!(
+            event_handler(ServiceControl::PowerEvent(PowerEventParam::QuerySuspend)),
+            ServiceControlHandlerResult::NoError
+        ));
+        assert!(matches!(
+            event_handler(ServiceControl::PowerEvent(PowerEventParam::Suspend)),
+            ServiceControlHandlerResult::NotImplemented
+        ));
+        assert!(matches!(
+            event_handler(ServiceControl::HardwareProfileChange(
+                HardwareProfileChangeParam::QueryChangeConfig
+            )),
+            ServiceControlHandlerResult::Other(5)
+        ));
+    }
+
+    #[test]
+    fn test_control_dispatcher() {
+        let stopped = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let stopped_clone = stopped.clone();
+        let mut dispatcher = ControlDispatcher::new()
+            .on_stop(move || {
+                stopped_clone.store(true, std::sync::atomic::Ordering::SeqCst);
+            })
+            .on_pause(|| {})
+            .on_power_event(|_| ServiceControlHandlerResult::Other(5));
+
+        assert_eq!(
+            dispatcher.controls_accepted(),
+            ServiceControlAccept::STOP | ServiceControlAccept::POWER_EVENT
+        );
+        assert!(matches!(
+            dispatcher.dispatch(ServiceControl::Interrogate),
+            ServiceControlHandlerResult::NoError
+        ));
+        assert!(matches!(
+            dispatcher.dispatch(ServiceControl::Stop),
+            ServiceControlHandlerResult::NoError
+        ));
+        assert!(stopped.load(std::sync::atomic::Ordering::SeqCst));
+        assert!(matches!(
+            dispatcher.dispatch(ServiceControl::PowerEvent(PowerEventParam::QuerySuspend)),
+            ServiceControlHandlerResult::Other(5)
+        ));
+        assert!(matches!(
+            dispatcher.dispatch(ServiceControl::Shutdown),
+            ServiceControlHandlerResult::NotImplemented
+        ));
+    }
+
+    #[test]
+    fn test_control_dispatcher_prefers_preshutdown() {
+        let dispatcher = ControlDispatcher::new()
+            .on_stop(|| {})
+            .on_shutdown(|| {})
+            .on_preshutdown(|| {});
+        assert_eq!(
+            dispatcher.controls_accepted(),
+            ServiceControlAccept::STOP | ServiceControlAccept::PRESHUTDOWN
+        );
+
+        let dispatcher = ControlDispatcher::new().on_shutdown(|| {});
+        assert_eq!(
+            dispatcher.controls_accepted(),
+            ServiceControlAccept::SHUTDOWN
+        );
+    }
+
+    #[test]
+    fn test_forwarded_is_reported_as_success() {
+        assert_eq!(ServiceControlHandlerResult::Forwarded.to_raw(), NO_ERROR);
+    }
+
+    #[test]
+    fn test_guarded_handler_survives_stop() {
+        let mut stops = 0;
+        let (id, handler) = insert_guarded_handler(move |control_event| match control_event {
+            ServiceControl::Stop => {
+                stops += 1;
+                ServiceControlHandlerResult::Other(stops)
+            }
+            _ => ServiceControlHandlerResult::NotImplemented,
+        });
+        let call = |control| unsafe { handler.unwrap()(control, 0, std::ptr::null_mut(), id as _) };
+
+        assert_eq!(call(Services::SERVICE_CONTROL_STOP), 1);
+        assert_eq!(call(Services::SERVICE_CONTROL_STOP), 2);
+        assert_eq!(call(Services::SERVICE_CONTROL_INTERROGATE), NO_ERROR);
+
+        drop(HandlerGuard {
+            service_name: WideCString::from_str("windows_service_test_guard").unwrap(),
+            id,
+        });
+        assert_eq!(
+            call(Services::SERVICE_CONTROL_STOP),
+            ERROR_CALL_NOT_IMPLEMENTED
+        );
+        assert_eq!(call(Services::SERVICE_CONTROL_INTERROGATE), NO_ERROR);
+    }
+
+    #[test]
+    fn test_guarded_handler_dropped_while_in_flight() {
+        struct DropFlag(Arc<std::sync::atomic::AtomicBool>);
+        impl Drop for DropFlag {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let released = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let drop_flag = DropFlag(Arc::clone(&released));
+        let (entered_tx, entered_rx) = mpsc::channel();
+        let (resume_tx, resume_rx) = mpsc::channel::<()>();
+        let (id, handler) = insert_guarded_handler(move |_| {
+            let _ = &drop_flag;
+            entered_tx.send(()).unwrap();
+            resume_rx.recv().unwrap();
+            ServiceControlHandlerResult::NoError
+        });
+
+        let dispatcher = thread::spawn(move || unsafe {
+            handler.unwrap()(
+                Services::SERVICE_CONTROL_STOP,
+                0,
+                std::ptr::null_mut(),
+                id as _,
+            )
+        });
+        entered_rx.recv().unwrap();
+
+        drop(HandlerGuard {
+            service_name: WideCString::from_str("windows_service_test_guard").unwrap(),
+            id,
+        });
+        assert!(!released.load(Ordering::SeqCst));
+
+        resume_tx.send(()).unwrap();
+        assert_eq!(dispatcher.join().unwrap(), NO_ERROR);
+        assert!(released.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_handler_result_from_win32_error() {
+        assert!(matches!(
+            ServiceControlHandlerResult::from_win32_error(NO_ERROR),
+            ServiceControlHandlerResult::NoError
+        ));
+        assert!(matches!(
+            ServiceControlHandlerResult::from_win32_error(ERROR_CALL_NOT_IMPLEMENTED),
+            ServiceControlHandlerResult::NotImplemented
+        ));
+        assert!(matches!(
+            ServiceControlHandlerResult::from_win32_error(ERROR_BUSY),
+            ServiceControlHandlerResult::Other(ERROR_BUSY)
+        ));
+        assert_eq!(
+            ServiceControlHandlerResult::BROADCAST_QUERY_DENY.to_raw(),
+            0x424D_5144
+        );
+    }
+}
@@ -1,4 +1,6 @@
+use std::cell::RefCell;
 use std::ffi::{OsStr, OsString};
+use std::sync::atomic::{AtomicPtr, Ordering};
 use std::{io, ptr};
 
 use widestring::{WideCStr, WideCString};
@@ -107,21 +109,111 @@ pub fn start(
 
     let result = unsafe { Services::StartServiceCtrlDispatcherW(service_table.as_ptr()) };
     if result == 0 {
-        Err(Error::Winapi(io::Error::last_os_error()))
+        Err(Error::winapi(io::Error::last_os_error()))
     } else {
         Ok(())
     }
 }
 
+/// The type of the closure passed to [`start_closure`].
+type ServiceMainClosure = Box<dyn FnOnce(Vec<OsString>) + Send + 'static>;
+
+/// The closure passed to [`start_closure`], waiting to be called by [`closure_service_main`].
+static SERVICE_MAIN_CLOSURE: AtomicPtr<ServiceMainClosure> = AtomicPtr::new(ptr::null_mut());
+
+/// Start service control dispatcher with a closure as the service entry point.
+///
+/// This behaves like [`start`], but does not require defining the entry point with
+/// [`define_windows_service!`]. The system calls `service_main` on a background thread with the
+/// parsed service arguments, which is why it has to be `Send` and `'static`.
+///
+/// `service_main` is called at most once. If the dispatcher fails to start, or returns without
+/// starting the service, `service_main` is dropped without being called.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::ffi::OsString;
+/// use windows_service::service_dispatcher;
+///
+/// fn main() -> windows_service::Result<()> {
+///     let config_path = OsString::from(r"C:\ProgramData\myservice\config.toml");
+///     service_dispatcher::start_closure("myservice", move |arguments: Vec<OsString>| {
+///         // The entry point where execution will start on a background thread.
+///         println!("{:?} {:?}", config_path, arguments);
+///     })?;
+///     Ok(())
+/// }
+/// ```
+pub fn start_closure<F>(service_name: impl AsRef<OsStr>, service_main: F) -> Result<()>
+where
+    F: FnOnce(Vec<OsString>) + Send + 'static,
+{
+    let closure: ServiceMainClosure = Box::new(service_main);
+    let previous = SERVICE_MAIN_CLOSURE.swap(Box::into_raw(Box::new(closure)), Ordering::AcqRel);
+    drop_closure(previous);
+
+    let result = start(service_name, closure_service_main);
+
+    // Release the closure if the system never called it.
+    drop_closure(SERVICE_MAIN_CLOSURE.swap(ptr::null_mut(), Ordering::AcqRel));
+    result
+}
+
+/// Static callback used by the system to bootstrap a service started with [`start_closure`].
+extern "system" fn closure_service_main(
+    num_service_arguments: u32,
+    service_arguments: *mut *mut u16,
+) {
+    let closure = SERVICE_MAIN_CLOSURE.swap(ptr::null_mut(), Ordering::AcqRel);
+    if !closure.is_null() {
+        let arguments =
+            unsafe { parse_service_arguments(num_service_arguments, service_arguments) };
+        let closure: Box<ServiceMainClosure> = unsafe { Box::from_raw(closure) };
+        closure(arguments);
+    }
+}
+
+fn drop_closure(closure: *mut ServiceMainClosure) {
+    if !closure.is_null() {
+        let _: Box<ServiceMainClosure> = unsafe { Box::from_raw(closure) };
+    }
+}
+
+thread_local! {
+    /// The name passed to `service_main` on this thread, see [`current_service_name`].
+    static CURRENT_SERVICE_NAME: RefCell<Option<OsString>> = const { RefCell::new(None) };
+}
+
+/// Returns the name under which the system invoked `service_main` on the calling thread.
+///
+/// This lets a binary that is installed as several services, or that also runs in other roles,
+/// find out which service it is running as. The name is the first argument the system passes
+/// to `service_main`, which is the name of the service being started.
+///
+/// The system runs each service of a shared process in its own `service_main` thread, so the
+/// name is kept per thread. Call this from `service_main` and pass the name on to any threads
+/// the service spawns.
+///
+/// Returns `None` if `service_main` has not been invoked on the calling thread, for example
+/// when the process does not run as a service or when called from another thread.
+pub fn current_service_name() -> Option<OsString> {
+    CURRENT_SERVICE_NAME.with(|name| name.borrow().clone())
+}
+
 /// Parse raw arguments received in `service_main` into `Vec<OsString>`.
 ///
 /// This is an implementation detail and *should not* be called directly!
 #[doc(hidden)]
 pub unsafe fn parse_service_arguments(argc: u32, argv: *mut *mut u16) -> Vec<OsString> {
-    (0..argc)
+    let arguments: Vec<OsString> = (0..argc)
         .map(|i| {
             let array_element_ptr: *mut *mut u16 = argv.offset(i as isize);
             WideCStr::from_ptr_str(*array_element_ptr).to_os_string()
         })
-        .collect()
+        .collect();
+    if let Some(service_name) = arguments.first() {
+        CURRENT_SERVICE_NAME.with(|name| *name.borrow_mut() = Some(service_name.clone()));
+    }
+    arguments
 }
@@ -1,12 +1,26 @@
+use std::collections::{HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::os::windows::ffi::OsStringExt;
-use std::{io, ptr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{cmp, io, ptr, thread};
 
-use widestring::WideCString;
-use windows_sys::Win32::System::Services;
+use widestring::{WideCStr, WideCString};
+use windows_sys::Win32::{
+    Foundation::{
+        ERROR_INVALID_PARAMETER, ERROR_MORE_DATA, ERROR_SERVICE_DOES_NOT_EXIST,
+        ERROR_SHUTDOWN_IN_PROGRESS,
+    },
+    System::Services,
+};
 
 use crate::sc_handle::ScHandle;
-use crate::service::{to_wide, RawServiceInfo, Service, ServiceAccess, ServiceInfo};
+use crate::service::{
+    to_wide, ConfigDifference, RawServiceInfo, Service, ServiceAccess, ServiceDependency,
+    ServiceFailureActions, ServiceFlags, ServiceInfo, ServiceManifest, ServiceName, ServiceSidType,
+    ServiceStartType, ServiceStatus, ServiceTrigger, ServiceType,
+};
 use crate::{Error, Result};
 
 bitflags::bitflags! {
@@ -22,14 +36,305 @@ bitflags::bitflags! {
         /// Can enumerate services or receive notifications.
         const ENUMERATE_SERVICE = Services::SC_MANAGER_ENUMERATE_SERVICE;
 
+        /// Can query the lock status of the service database.
+        const QUERY_LOCK_STATUS = Services::SC_MANAGER_QUERY_LOCK_STATUS;
+
         /// Includes all possible access rights.
         const ALL_ACCESS = Services::SC_MANAGER_ALL_ACCESS;
     }
 }
 
+impl std::fmt::Display for ServiceManagerAccess {
+    /// Formats the set flags by name, for instance `CONNECT | CREATE_SERVICE`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        bitflags::parser::to_writer(self, f)
+    }
+}
+
+/// Enum describing the state of the services to enumerate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ServiceStateFilter {
+    /// Services in any state other than [`ServiceState::Stopped`].
+    ///
+    /// [`ServiceState::Stopped`]: crate::service::ServiceState::Stopped
+    Active = Services::SERVICE_ACTIVE,
+    /// Services in the [`ServiceState::Stopped`] state.
+    ///
+    /// [`ServiceState::Stopped`]: crate::service::ServiceState::Stopped
+    Inactive = Services::SERVICE_INACTIVE,
+    /// Services in any state.
+    All = Services::SERVICE_STATE_ALL,
+}
+
+impl ServiceStateFilter {
+    pub fn to_raw(&self) -> u32 {
+        *self as u32
+    }
+}
+
+/// A service returned by [`ServiceManager::enumerate_services`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServiceEntry {
+    /// Service name
+    pub name: OsString,
+
+    /// User-friendly service name, empty if the service does not have one.
+    pub display_name: OsString,
+
+    /// The service status at the time of enumeration
+    pub status: ServiceStatus,
+
+    /// Flags describing the process that hosts the service
+    pub service_flags: ServiceFlags,
+}
+
+/// A service as read from the system, borrowing the names from the enumeration buffer.
+///
+/// Passed to the predicate of [`ServiceManager::enumerate_services_filtered`], so that entries
+/// can be inspected without copying the names. Use [`ServiceEntryRef::to_entry`] to get an
+/// owned [`ServiceEntry`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServiceEntryRef<'a> {
+    /// Service name
+    pub name: &'a WideCStr,
+
+    /// User-friendly service name, empty if the service does not have one.
+    pub display_name: &'a WideCStr,
+
+    /// The service status at the time of enumeration
+    pub status: ServiceStatus,
+
+    /// Flags describing the process that hosts the service
+    pub service_flags: ServiceFlags,
+}
+
+impl<'a> ServiceEntryRef<'a> {
+    /// Tries to parse a `ENUM_SERVICE_STATUS_PROCESSW` into a [`ServiceEntryRef`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `dwCurrentState` field does not represent a valid
+    /// [`ServiceState`].
+    ///
+    /// # Safety
+    ///
+    /// `lpServiceName` must be a proper null terminated wide C string. `lpDisplayName` must be
+    /// either null or a proper null terminated wide C string. Both must outlive `'a`.
+    ///
+    /// [`ServiceState`]: crate::service::ServiceState
+    pub unsafe fn from_raw(
+        raw: Services::ENUM_SERVICE_STATUS_PROCESSW,
+    ) -> Result<ServiceEntryRef<'a>> {
+        let display_name = ptr::NonNull::new(raw.lpDisplayName)
+            .map(|wrapped_ptr| WideCStr::from_ptr_str(wrapped_ptr.as_ptr()))
+            .unwrap_or_else(|| widestring::u16cstr!(""));
+        Ok(ServiceEntryRef {
+            name: WideCStr::from_ptr_str(raw.lpServiceName),
+            display_name,
+            status: ServiceStatus::from_raw_ex(raw.ServiceStatusProcess)
+                .map_err(|e| Error::ParseValue("service status", e))?,
+            service_flags: ServiceFlags::from_bits_truncate(
+                raw.ServiceStatusProcess.dwServiceFlags,
+            ),
+        })
+    }
+
+    /// Copy the names into an owned [`ServiceEntry`].
+    pub fn to_entry(&self) -> ServiceEntry {
+        ServiceEntry {
+            name: self.name.to_os_string(),
+            display_name: self.display_name.to_os_string(),
+            status: self.status.clone(),
+            service_flags: self.service_flags,
+        }
+    }
+}
+
+impl ServiceEntry {
+    /// Returns `true` if the service runs in a system process that must always be running.
+    ///
+    /// Terminating the process of such a service would take down the system, so the
+    /// [`ServiceStatus::process_id`] must not be used to kill it.
+    pub fn runs_in_system_process(&self) -> bool {
+        self.service_flags
+            .contains(ServiceFlags::RUNS_IN_SYSTEM_PROCESS)
+    }
+
+    /// Tries to parse a `ENUM_SERVICE_STATUS_PROCESSW` into Rust [`ServiceEntry`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `dwCurrentState` field does not represent a valid
+    /// [`ServiceState`].
+    ///
+    /// # Safety
+    ///
+    /// `lpServiceName` must be a proper null terminated wide C string. `lpDisplayName` must be
+    /// either null or a proper null terminated wide C string.
+    ///
+    /// The strings are copied, so the returned entry does not borrow from the memory that `raw`
+    /// points into.
+    ///
+    /// [`ServiceState`]: crate::service::ServiceState
+    pub unsafe fn from_raw(raw: Services::ENUM_SERVICE_STATUS_PROCESSW) -> Result<ServiceEntry> {
+        ServiceEntryRef::from_raw(raw).map(|entry| entry.to_entry())
+    }
+}
+
+/// The security relevant parts of a service configuration, returned by
+/// [`ServiceManager::audit_services`].
+///
+/// Fields are `None` if the service could not be opened or the corresponding part of the
+/// configuration could not be queried, for instance due to insufficient access rights.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServiceAudit {
+    /// Service name
+    pub name: OsString,
+
+    /// The account that the service runs as.
+    pub account_name: Option<OsString>,
+
+    /// The service startup options
+    pub start_type: Option<ServiceStartType>,
+
+    /// How the service SID is added to the service process token.
+    pub sid_type: Option<ServiceSidType>,
+
+    /// Whether the executable path contains whitespace but is not quoted, see
+    /// [`ServiceConfig::has_unquoted_path_vulnerability`].
+    ///
+    /// [`ServiceConfig::has_unquoted_path_vulnerability`]: crate::service::ServiceConfig::has_unquoted_path_vulnerability
+    pub has_unquoted_path_vulnerability: Option<bool>,
+}
+
+/// The outcome of an operation on a single service, returned by
+/// [`ServiceManager::for_each_service`].
+#[derive(Debug)]
+pub struct ServiceOpResult<T> {
+    /// Service name
+    pub name: OsString,
+
+    /// The value returned by the operation, or the error that occurred when opening the service
+    /// or running the operation.
+    pub result: Result<T>,
+
+    /// How long it took to open the service and run the operation.
+    pub elapsed: Duration,
+}
+
+/// A change made, or to be made, to a service to match a [`ServiceManifest`].
+///
+/// Returned by [`ServiceManager::diff_manifest`] and [`ServiceManager::reconcile_manifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ManifestChange {
+    /// The service does not exist and is created.
+    Create,
+    /// The basic configuration differs and is changed.
+    Config(ConfigDifference),
+    /// The description differs.
+    Description {
+        expected: OsString,
+        actual: OsString,
+    },
+    /// Whether the start is delayed differs.
+    DelayedAutoStart { expected: bool, actual: bool },
+    /// The SID type differs.
+    SidType {
+        expected: ServiceSidType,
+        actual: ServiceSidType,
+    },
+    /// The failure actions differ.
+    FailureActions {
+        expected: ServiceFailureActions,
+        actual: ServiceFailureActions,
+    },
+    /// The triggers differ.
+    Triggers {
+        expected: Vec<ServiceTrigger>,
+        actual: Vec<ServiceTrigger>,
+    },
+}
+
+/// The lock status of the service database, returned by [`ServiceManager::query_lock_status`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScmLockStatus {
+    /// Whether the service database is locked.
+    pub is_locked: bool,
+
+    /// The account that holds the lock, for instance `.\NT Service Control Manager` while the
+    /// service control manager is starting a service. `None` if the database is not locked.
+    pub owner: Option<OsString>,
+
+    /// How long the database has been locked.
+    pub duration: Duration,
+}
+
+impl ScmLockStatus {
+    /// Tries to parse a `QUERY_SERVICE_LOCK_STATUSW` into Rust [`ScmLockStatus`].
+    ///
+    /// # Safety
+    ///
+    /// `lpLockOwner` must be either null or a proper null terminated wide C string.
+    unsafe fn from_raw(raw: Services::QUERY_SERVICE_LOCK_STATUSW) -> Self {
+        let is_locked = raw.fIsLocked != 0;
+        let owner = ptr::NonNull::new(raw.lpLockOwner)
+            .map(|wrapped_ptr| WideCStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string())
+            .filter(|owner| is_locked && !owner.is_empty());
+        ScmLockStatus {
+            is_locked,
+            owner,
+            duration: Duration::from_secs(u64::from(raw.dwLockDuration)),
+        }
+    }
+}
+
+/// As per docs, the maximum size of data buffer used by QueryServiceLockStatusW is 8K
+const MAX_LOCK_STATUS_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Sort the service entries by service name.
+///
+/// Service names are compared ignoring the ASCII case, the same way the service control manager
+/// compares them.
+pub fn sorted_by_name(mut entries: Vec<ServiceEntry>) -> Vec<ServiceEntry> {
+    entries.sort_by_cached_key(|entry| entry.name.to_ascii_lowercase());
+    entries
+}
+
+/// Error codes that [`ServiceManager::local_computer_with_retry`] treats as transient.
+///
+/// * `RPC_S_SERVER_UNAVAILABLE` (1722) - The service control manager is not running yet, or is
+///   no longer running.
+/// * `RPC_S_SERVER_TOO_BUSY` (1723) - The service control manager is too busy to accept the
+///   connection.
+/// * `ERROR_SHUTDOWN_IN_PROGRESS` (1115) - The system is shutting down.
+pub const TRANSIENT_CONNECT_ERRORS: &[u32] = &[
+    RPC_S_SERVER_UNAVAILABLE,
+    RPC_S_SERVER_TOO_BUSY,
+    ERROR_SHUTDOWN_IN_PROGRESS,
+];
+
+const RPC_S_SERVER_UNAVAILABLE: u32 = 1722;
+const RPC_S_SERVER_TOO_BUSY: u32 = 1723;
+
+/// The interval between connection attempts in [`ServiceManager::local_computer_with_retry`].
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+fn is_transient_connect_error(error: &io::Error) -> bool {
+    error.raw_os_error().map_or(false, |code| {
+        TRANSIENT_CONNECT_ERRORS.contains(&(code as u32))
+    })
+}
+
 /// Service manager.
+///
+/// Opening a connection to the service control manager is relatively expensive, so tools that
+/// perform many operations should keep a single `ServiceManager` around and reuse it. The
+/// connection can be used from any thread, and cloning the manager is cheap since all clones share
+/// the same connection, which is closed when the last clone is dropped.
+#[derive(Clone)]
 pub struct ServiceManager {
-    manager_handle: ScHandle,
+    manager_handle: Arc<ScHandle>,
 }
 
 impl ServiceManager {
@@ -58,10 +363,10 @@ impl ServiceManager {
         };
 
         if handle.is_null() {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
             Ok(ServiceManager {
-                manager_handle: unsafe { ScHandle::new(handle) },
+                manager_handle: Arc::new(unsafe { ScHandle::new(handle) }),
             })
         }
     }
@@ -80,6 +385,41 @@ impl ServiceManager {
         ServiceManager::new(None::<&OsStr>, database, request_access)
     }
 
+    /// Connect to local services database, retrying while the service control manager is
+    /// temporarily unavailable.
+    ///
+    /// Early during boot the service control manager may not accept connections yet, and during
+    /// system shutdown it may reject new connections. This retries the connection for as long as
+    /// it fails with one of the [`TRANSIENT_CONNECT_ERRORS`] and `timeout` has not elapsed. Any
+    /// other error is returned immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - The name of database to connect to. Pass `None` to connect to active
+    ///   database.
+    /// * `request_access` - Desired access permissions.
+    /// * `timeout` - How long to keep retrying before giving up and returning the last error.
+    pub fn local_computer_with_retry(
+        database: Option<impl AsRef<OsStr>>,
+        request_access: ServiceManagerAccess,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let database = database.as_ref().map(|database| database.as_ref());
+        let start = Instant::now();
+        loop {
+            match ServiceManager::local_computer(database, request_access) {
+                Err(Error::Winapi(e)) if is_transient_connect_error(&e) => {
+                    let elapsed = start.elapsed();
+                    if elapsed >= timeout {
+                        return Err(Error::Winapi(e));
+                    }
+                    thread::sleep(CONNECT_RETRY_INTERVAL.min(timeout - elapsed));
+                }
+                result => return result,
+            }
+        }
+    }
+
     /// Connect to remote services database.
     ///
     /// # Arguments
@@ -104,6 +444,36 @@ impl ServiceManager {
     ///   registry.
     /// * `service_access` - Desired access permissions for the returned [`Service`] instance.
     ///
+    /// # Access rights of the returned handle
+    ///
+    /// `service_access` only controls what the returned [`Service`] can be used for, it has no
+    /// effect on the service that gets created. Callers that intend to use the service right
+    /// after creating it, for example to set a description or failure actions, should request
+    /// the union of all the rights they need, such as
+    /// `ServiceAccess::QUERY_STATUS | ServiceAccess::CHANGE_CONFIG | ServiceAccess::START`.
+    /// This avoids having to open the service a second time.
+    ///
+    /// # Per-user services
+    ///
+    /// Passing [`ServiceType::USER_OWN_PROCESS`] or [`ServiceType::USER_SHARE_PROCESS`] creates a
+    /// per-user service template rather than a service that runs on its own. The system creates
+    /// an instance of the template for each user that signs in, named `<name>_<LUID>`, and runs
+    /// it under that user's account, so `account_name` and `account_password` of the template
+    /// are not used for the instances. Whether instances are created is further controlled by the
+    /// `UserServiceFlags` value under the service registry key.
+    ///
+    /// Per-user services are available starting with Windows 10, version 1607. They cannot be
+    /// combined with driver types or [`ServiceType::INTERACTIVE_PROCESS`], which is reported as
+    /// [`Error::InvalidUserServiceType`]. When the system rejects the template, the error is
+    /// reported as [`Error::UserServiceNotSupported`].
+    ///
+    /// Please refer to MSDN for more info:\
+    /// <https://docs.microsoft.com/en-us/windows/application-management/per-user-services-in-windows>
+    ///
+    /// [`ServiceType::USER_OWN_PROCESS`]: crate::service::ServiceType::USER_OWN_PROCESS
+    /// [`ServiceType::USER_SHARE_PROCESS`]: crate::service::ServiceType::USER_SHARE_PROCESS
+    /// [`ServiceType::INTERACTIVE_PROCESS`]: crate::service::ServiceType::INTERACTIVE_PROCESS
+    ///
     /// # Example
     ///
     /// ```rust,no_run
@@ -169,12 +539,226 @@ impl ServiceManager {
         };
 
         if service_handle.is_null() {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            let error = io::Error::last_os_error();
+            if service_info.service_type.is_user_service()
+                && error.raw_os_error() == Some(ERROR_INVALID_PARAMETER as i32)
+            {
+                Err(Error::UserServiceNotSupported(error))
+            } else {
+                Err(Error::winapi(error))
+            }
         } else {
             Ok(Service::new(unsafe { ScHandle::new(service_handle) }))
         }
     }
 
+    /// Create the service described by the manifest, or update the existing service to match
+    /// it.
+    ///
+    /// Applying the same manifest again is a no-op: the basic configuration is only changed if
+    /// [`ServiceInfo::differences`] reports a difference, and each optional part of the manifest
+    /// that is not `None` is only set if it differs from the stored value. Note that the account
+    /// password cannot be queried, so it is only set along with other changes to the basic
+    /// configuration.
+    ///
+    /// The returned service is opened with [`ServiceAccess::QUERY_STATUS`],
+    /// [`ServiceAccess::QUERY_CONFIG`], [`ServiceAccess::CHANGE_CONFIG`] and
+    /// [`ServiceAccess::START`], which are also the permissions required on an existing service.
+    /// Creating a service requires [`ServiceManagerAccess::CREATE_SERVICE`].
+    ///
+    /// # Errors
+    ///
+    /// The [`ServiceInfo`] of the manifest is checked with [`ServiceInfo::validate`] before the
+    /// service is opened or created, and its error is returned if it is not valid.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::ffi::OsString;
+    /// use windows_service::service::{
+    ///     ServiceErrorControl, ServiceInfo, ServiceManifest, ServiceStartType, ServiceType,
+    /// };
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(
+    ///     None::<&str>,
+    ///     ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    /// )?;
+    /// let mut manifest = ServiceManifest::new(ServiceInfo {
+    ///     name: OsString::from("my_service"),
+    ///     display_name: OsString::from("My service"),
+    ///     service_type: ServiceType::OWN_PROCESS,
+    ///     start_type: ServiceStartType::AutoStart,
+    ///     error_control: ServiceErrorControl::Normal,
+    ///     executable_path: "C:\\my_service.exe".into(),
+    ///     launch_arguments: vec![],
+    ///     dependencies: vec![],
+    ///     account_name: None,
+    ///     account_password: None,
+    /// });
+    /// manifest.description = Some(OsString::from("Does my things"));
+    /// manifest.delayed_auto_start = Some(true);
+    /// manager.apply_manifest(&manifest)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn apply_manifest(&self, manifest: &ServiceManifest) -> Result<Service> {
+        let (service, _) = self.reconcile(manifest, true)?;
+        Ok(service.expect("the service is always opened or created when applying a manifest"))
+    }
+
+    /// Compute the changes that [`ServiceManager::reconcile_manifest`] would make, without
+    /// changing anything.
+    ///
+    /// If the service does not exist, only [`ManifestChange::Create`] is returned.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`] on the service.
+    pub fn diff_manifest(&self, manifest: &ServiceManifest) -> Result<Vec<ManifestChange>> {
+        self.reconcile(manifest, false).map(|(_, changes)| changes)
+    }
+
+    /// Create or update the service to match the manifest, like
+    /// [`ServiceManager::apply_manifest`], and return the changes that were made.
+    ///
+    /// An empty list means that the service already matched the manifest. When the service is
+    /// created, [`ManifestChange::Create`] is followed by the optional parts of the manifest that
+    /// differ from the defaults of the new service.
+    ///
+    /// Required permissions: the same as for [`ServiceManager::apply_manifest`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceManifest;
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn load_manifest() -> ServiceManifest { unimplemented!() }
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manifest = load_manifest();
+    /// let manager = ServiceManager::local_computer(
+    ///     None::<&str>,
+    ///     ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    /// )?;
+    /// for change in manager.diff_manifest(&manifest)? {
+    ///     println!("would change: {:?}", change);
+    /// }
+    /// let changes = manager.reconcile_manifest(&manifest)?;
+    /// println!("made {} changes", changes.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reconcile_manifest(&self, manifest: &ServiceManifest) -> Result<Vec<ManifestChange>> {
+        self.reconcile(manifest, true).map(|(_, changes)| changes)
+    }
+
+    /// Private helper that compares the service with the manifest, and if `apply` is `true`
+    /// creates or updates it to match. Returns the service, unless it does not exist and is not
+    /// created, along with the changes.
+    fn reconcile(
+        &self,
+        manifest: &ServiceManifest,
+        apply: bool,
+    ) -> Result<(Option<Service>, Vec<ManifestChange>)> {
+        if apply {
+            manifest.info.validate()?;
+        }
+        let service_access = if apply {
+            ServiceAccess::QUERY_STATUS
+                | ServiceAccess::QUERY_CONFIG
+                | ServiceAccess::CHANGE_CONFIG
+                | ServiceAccess::START
+        } else {
+            ServiceAccess::QUERY_CONFIG
+        };
+
+        let mut changes = Vec::new();
+        let service = match self.open_service(&manifest.info.name, service_access) {
+            Ok(service) => {
+                let differences = manifest.info.differences(&service.query_config()?)?;
+                if apply && !differences.is_empty() {
+                    service.change_config(&manifest.info)?;
+                }
+                changes.extend(differences.into_iter().map(ManifestChange::Config));
+                service
+            }
+            Err(Error::Winapi(e))
+                if e.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST as i32) =>
+            {
+                changes.push(ManifestChange::Create);
+                if !apply {
+                    return Ok((None, changes));
+                }
+                self.create_service(&manifest.info, service_access)?
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Each component is queried on its own, so that a failed query is reported as an error
+        // rather than as a difference.
+        if let Some(description) = &manifest.description {
+            let actual = service.query_description()?;
+            if &actual != description {
+                if apply {
+                    service.set_description(description)?;
+                }
+                changes.push(ManifestChange::Description {
+                    expected: description.clone(),
+                    actual,
+                });
+            }
+        }
+        if let Some(delayed_auto_start) = manifest.delayed_auto_start {
+            let actual = service.query_delayed_auto_start()?;
+            if actual != delayed_auto_start {
+                if apply {
+                    service.set_delayed_auto_start(delayed_auto_start)?;
+                }
+                changes.push(ManifestChange::DelayedAutoStart {
+                    expected: delayed_auto_start,
+                    actual,
+                });
+            }
+        }
+        if let Some(sid_type) = manifest.sid_type {
+            let actual = service.get_config_service_sid_info()?;
+            if actual != sid_type {
+                if apply {
+                    service.set_config_service_sid_info(sid_type)?;
+                }
+                changes.push(ManifestChange::SidType {
+                    expected: sid_type,
+                    actual,
+                });
+            }
+        }
+        if let Some(failure_actions) = &manifest.failure_actions {
+            let actual = service.get_failure_actions()?;
+            if !failure_actions_match(failure_actions, &actual) {
+                if apply {
+                    service.update_failure_actions(failure_actions.clone())?;
+                }
+                changes.push(ManifestChange::FailureActions {
+                    expected: failure_actions.clone(),
+                    actual,
+                });
+            }
+        }
+        if let Some(triggers) = &manifest.triggers {
+            let actual = service.query_triggers()?;
+            if &actual != triggers {
+                if apply {
+                    service.set_triggers(triggers)?;
+                }
+                changes.push(ManifestChange::Triggers {
+                    expected: triggers.clone(),
+                    actual,
+                });
+            }
+        }
+        Ok((Some(service), changes))
+    }
+
     /// Open an existing service.
     ///
     /// # Arguments
@@ -201,6 +785,51 @@ impl ServiceManager {
     ) -> Result<Service> {
         let service_name = WideCString::from_os_str(name)
             .map_err(|_| Error::ArgumentHasNulByte("service name"))?;
+        self.open_service_wide(&service_name, request_access)
+    }
+
+    /// Open an existing service using a pre-converted [`ServiceName`].
+    ///
+    /// This behaves like [`ServiceManager::open_service`] but does not convert or allocate the
+    /// service name on each call, which helps tools that repeatedly open the same services.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The service name.
+    /// * `request_access` - Desired permissions for the returned [`Service`] instance.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::{ServiceAccess, ServiceName};
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let names = ["Dhcp", "Dnscache", "W32Time"]
+    ///     .iter()
+    ///     .map(ServiceName::new)
+    ///     .collect::<windows_service::Result<Vec<_>>>()?;
+    /// for name in &names {
+    ///     let service = manager.open_service_with_name(name, ServiceAccess::QUERY_STATUS)?;
+    ///     println!("{:?}: {:?}", name, service.state()?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn open_service_with_name(
+        &self,
+        name: &ServiceName,
+        request_access: ServiceAccess,
+    ) -> Result<Service> {
+        self.open_service_wide(name.as_wide_c_str(), request_access)
+    }
+
+    fn open_service_wide(
+        &self,
+        service_name: &WideCStr,
+        request_access: ServiceAccess,
+    ) -> Result<Service> {
         let service_handle = unsafe {
             Services::OpenServiceW(
                 self.manager_handle.raw_handle(),
@@ -210,7 +839,7 @@ impl ServiceManager {
         };
 
         if service_handle.is_null() {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
             Ok(Service::new(unsafe { ScHandle::new(service_handle) }))
         }
@@ -255,11 +884,1043 @@ impl ServiceManager {
         };
 
         if result == 0 {
-            Err(Error::Winapi(io::Error::last_os_error()))
+            Err(Error::winapi(io::Error::last_os_error()))
         } else {
             Ok(OsString::from_wide(
                 &buffer[..usize::try_from(buffer_len).unwrap()],
             ))
         }
     }
+
+    /// Enumerate the services of the given type and state.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    ///
+    /// # Ordering and duplicates
+    ///
+    /// The system makes no guarantees about the order of the returned services. Use
+    /// [`sorted_by_name`] when a stable order is needed, for instance to compare two snapshots.
+    ///
+    /// Services are retrieved from the system in chunks, so services that are created or deleted
+    /// while the enumeration is in progress may or may not be included in the result. Each service
+    /// name is however returned at most once, even if the service was reported in more than one
+    /// chunk.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceType;
+    /// use windows_service::service_manager::{
+    ///     sorted_by_name, ServiceManager, ServiceManagerAccess, ServiceStateFilter,
+    /// };
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+    /// let services = manager.enumerate_services(
+    ///     ServiceType::OWN_PROCESS | ServiceType::SHARE_PROCESS,
+    ///     ServiceStateFilter::All,
+    /// )?;
+    /// for service in sorted_by_name(services) {
+    ///     println!("{:?}: {:?}", service.name, service.status.current_state);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn enumerate_services(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+    ) -> Result<Vec<ServiceEntry>> {
+        self.enumerate_services_with_buffer(service_type, state, &mut Vec::new())
+    }
+
+    /// Enumerate the services of the given type and state, using `buffer` as scratch space.
+    ///
+    /// This behaves like [`ServiceManager::enumerate_services`], but reads the raw data from
+    /// the system into `buffer`, growing it as needed. Passing the same buffer to repeated
+    /// enumerations, for example in an agent that polls the services periodically, avoids
+    /// allocating it again every time. The returned entries do not borrow from the buffer.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceType;
+    /// use windows_service::service_manager::{
+    ///     ServiceManager, ServiceManagerAccess, ServiceStateFilter,
+    /// };
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+    /// let mut buffer = Vec::new();
+    /// loop {
+    ///     let services = manager.enumerate_services_with_buffer(
+    ///         ServiceType::OWN_PROCESS,
+    ///         ServiceStateFilter::Active,
+    ///         &mut buffer,
+    ///     )?;
+    ///     println!("{} services running", services.len());
+    ///     std::thread::sleep(std::time::Duration::from_secs(10));
+    /// }
+    /// # }
+    /// ```
+    pub fn enumerate_services_with_buffer(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+        buffer: &mut Vec<u8>,
+    ) -> Result<Vec<ServiceEntry>> {
+        self.enumerate_matching(service_type, state, buffer, |_| true)
+    }
+
+    /// Enumerate the services of the given type and state that match `predicate`.
+    ///
+    /// This behaves like [`ServiceManager::enumerate_services`], but evaluates `predicate` on
+    /// each entry as it is read from the system and only keeps the entries for which it returns
+    /// `true`. `predicate` is passed a [`ServiceEntryRef`] that borrows the names from the
+    /// enumeration buffer, so only the matching entries are copied. Tools that are only
+    /// interested in a few services thereby avoid copying all of them.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::{ServiceState, ServiceType};
+    /// use windows_service::service_manager::{
+    ///     ServiceManager, ServiceManagerAccess, ServiceStateFilter,
+    /// };
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+    /// let pending = manager.enumerate_services_filtered(
+    ///     ServiceType::OWN_PROCESS | ServiceType::SHARE_PROCESS,
+    ///     ServiceStateFilter::All,
+    ///     |entry| entry.status.current_state == ServiceState::StartPending,
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn enumerate_services_filtered(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+        predicate: impl FnMut(&ServiceEntryRef<'_>) -> bool,
+    ) -> Result<Vec<ServiceEntry>> {
+        self.enumerate_matching(service_type, state, &mut Vec::new(), predicate)
+    }
+
+    /// Private helper that collects the enumerated services that match `predicate`, skipping
+    /// duplicates. Only matching entries are recorded for the duplicate check.
+    fn enumerate_matching(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+        buffer: &mut Vec<u8>,
+        mut predicate: impl FnMut(&ServiceEntryRef<'_>) -> bool,
+    ) -> Result<Vec<ServiceEntry>> {
+        let mut entries = Vec::new();
+        let mut seen_names = HashSet::new();
+        self.enumerate_raw(service_type, state, buffer, |raw_entry| {
+            let entry_ref = unsafe { ServiceEntryRef::from_raw(raw_entry) }?;
+            if predicate(&entry_ref) {
+                let entry = entry_ref.to_entry();
+                if seen_names.insert(entry.name.to_ascii_lowercase()) {
+                    entries.push(entry);
+                }
+            }
+            Ok(())
+        })?;
+        Ok(entries)
+    }
+
+    /// Enumerate the services of the given type and state lazily.
+    ///
+    /// Unlike [`ServiceManager::enumerate_services`], services are read from the system one chunk
+    /// at a time as the iterator advances, so the first entries are available before the whole
+    /// enumeration completes. This lets, for example, a user interface display services
+    /// incrementally on machines with a large number of services. The crate does not depend on
+    /// any async runtime, async applications can drive the iterator from a blocking task and
+    /// forward the entries over a channel.
+    ///
+    /// The iterator stops after yielding an error. The same ordering and duplicate guarantees as
+    /// for [`ServiceManager::enumerate_services`] apply.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceType;
+    /// use windows_service::service_manager::{
+    ///     ServiceManager, ServiceManagerAccess, ServiceStateFilter,
+    /// };
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+    /// for service in manager.enumerate_services_iter(ServiceType::OWN_PROCESS, ServiceStateFilter::All)
+    /// {
+    ///     println!("{:?}", service?.name);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn enumerate_services_iter(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+    ) -> ServiceEntries<'_> {
+        ServiceEntries {
+            manager: self,
+            service_type,
+            state,
+            buffer: Vec::new(),
+            resume_handle: 0,
+            has_more: true,
+            chunk: Vec::new().into_iter(),
+            seen_names: HashSet::new(),
+        }
+    }
+
+    /// Query whether the service database is locked, by whom and for how long.
+    ///
+    /// Useful for reporting which account holds the lock when an operation fails with
+    /// `ERROR_SERVICE_DATABASE_LOCKED`.
+    ///
+    /// Required permission: [`ServiceManagerAccess::QUERY_LOCK_STATUS`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::QUERY_LOCK_STATUS)?;
+    /// let lock_status = manager.query_lock_status()?;
+    /// if let Some(owner) = lock_status.owner {
+    ///     println!(
+    ///         "locked by {} for {:?}",
+    ///         owner.to_string_lossy(),
+    ///         lock_status.duration
+    ///     );
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query_lock_status(&self) -> Result<ScmLockStatus> {
+        let mut data = vec![0u8; MAX_LOCK_STATUS_BUFFER_SIZE];
+        let mut bytes_needed: u32 = 0;
+
+        let success = unsafe {
+            Services::QueryServiceLockStatusW(
+                self.manager_handle.raw_handle(),
+                data.as_mut_ptr() as _,
+                data.len() as u32,
+                &mut bytes_needed,
+            )
+        };
+
+        if success == 0 {
+            Err(Error::winapi(io::Error::last_os_error()))
+        } else {
+            unsafe {
+                let raw_status = ptr::read_unaligned(
+                    data.as_ptr() as *const Services::QUERY_SERVICE_LOCK_STATUSW
+                );
+                Ok(ScmLockStatus::from_raw(raw_status))
+            }
+        }
+    }
+
+    /// Count the services of the given type and state.
+    ///
+    /// This performs the same enumeration as [`ServiceManager::enumerate_services`], but does
+    /// not copy the service names and statuses, which makes it cheaper when only the number of
+    /// services is needed, for instance for health metrics.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceType;
+    /// use windows_service::service_manager::{
+    ///     ServiceManager, ServiceManagerAccess, ServiceStateFilter,
+    /// };
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+    /// let running = manager.count_services(
+    ///     ServiceType::OWN_PROCESS | ServiceType::SHARE_PROCESS,
+    ///     ServiceStateFilter::Active,
+    /// )?;
+    /// println!("{} running services", running);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn count_services(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+    ) -> Result<usize> {
+        // Reading on from the resume handle does not report a service twice, so the entries of
+        // each chunk can be counted without looking at them.
+        let mut count = 0;
+        self.enumerate_raw(service_type, state, &mut Vec::new(), |_| {
+            count += 1;
+            Ok(())
+        })?;
+        Ok(count)
+    }
+
+    /// Create a [`ServiceEnumerator`] for repeatedly enumerating the services of the given type
+    /// and state.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    pub fn service_enumerator(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+    ) -> ServiceEnumerator<'_> {
+        ServiceEnumerator {
+            manager: self,
+            service_type,
+            state,
+            buffer: Vec::new(),
+            entries: Vec::new(),
+            seen_names: HashSet::new(),
+        }
+    }
+
+    /// Return the names of the win32 services configured to run as the given account.
+    ///
+    /// Account names are compared ignoring the ASCII case. Services running as LocalSystem are
+    /// usually configured with the `LocalSystem` account name.
+    ///
+    /// Services that cannot be opened or queried, for instance due to insufficient access rights,
+    /// are skipped.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+    /// let services = manager.services_running_as(r"NT AUTHORITY\LocalService")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn services_running_as(&self, account: impl AsRef<OsStr>) -> Result<Vec<OsString>> {
+        let account = account.as_ref();
+        let entries = self.enumerate_services(
+            ServiceType::OWN_PROCESS | ServiceType::SHARE_PROCESS,
+            ServiceStateFilter::All,
+        )?;
+
+        Ok(entries
+            .into_iter()
+            .filter(|entry| {
+                self.open_service(&entry.name, ServiceAccess::QUERY_CONFIG)
+                    .and_then(|service| service.query_config())
+                    .map(|config| {
+                        config
+                            .account_name
+                            .map_or(false, |name| name.eq_ignore_ascii_case(account))
+                    })
+                    .unwrap_or(false)
+            })
+            .map(|entry| entry.name)
+            .collect())
+    }
+
+    /// Enumerate the services of the given type and state and query the security relevant parts
+    /// of their configuration concurrently.
+    ///
+    /// The services are opened and queried on up to `max_threads` threads sharing this
+    /// connection, which is considerably faster than querying them one by one on machines with
+    /// hundreds of services. The audits are returned in enumeration order. Failing to open or
+    /// query a service does not abort the audit, the corresponding fields are `None` instead.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`], and
+    /// [`ServiceAccess::QUERY_CONFIG`] on each service.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceType;
+    /// use windows_service::service_manager::{
+    ///     ServiceManager, ServiceManagerAccess, ServiceStateFilter,
+    /// };
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+    /// let audits = manager.audit_services(
+    ///     ServiceType::OWN_PROCESS | ServiceType::SHARE_PROCESS,
+    ///     ServiceStateFilter::All,
+    ///     8,
+    /// )?;
+    /// for audit in audits {
+    ///     if audit.has_unquoted_path_vulnerability == Some(true) {
+    ///         println!("{} has an unquoted path", audit.name.to_string_lossy());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn audit_services(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+        max_threads: usize,
+    ) -> Result<Vec<ServiceAudit>> {
+        let names: Arc<Vec<OsString>> = Arc::new(
+            self.enumerate_services(service_type, state)?
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect(),
+        );
+        let next_index = Arc::new(AtomicUsize::new(0));
+
+        let workers: Vec<_> = (0..cmp::min(max_threads.max(1), names.len()))
+            .map(|_| {
+                let manager = self.clone();
+                let names = Arc::clone(&names);
+                let next_index = Arc::clone(&next_index);
+                thread::spawn(move || {
+                    let mut audits = Vec::new();
+                    loop {
+                        let index = next_index.fetch_add(1, Ordering::Relaxed);
+                        match names.get(index) {
+                            Some(name) => audits.push((index, manager.audit_service(name))),
+                            None => return audits,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut audits = Vec::with_capacity(names.len());
+        for worker in workers {
+            match worker.join() {
+                Ok(worker_audits) => audits.extend(worker_audits),
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+        audits.sort_unstable_by_key(|(index, _)| *index);
+        Ok(audits.into_iter().map(|(_, audit)| audit).collect())
+    }
+
+    /// Open each of the named services and run `op` on it.
+    ///
+    /// The services are processed one by one in the given order. Failing to open a service or
+    /// an error returned by `op` does not abort the batch, the error is recorded in the result
+    /// for that service instead. Each result also records how long the service took to process,
+    /// which is useful for spotting slow services in fleet automation.
+    ///
+    /// Required permission: the `access` requested for each service, which needs to cover the
+    /// permissions required by `op`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceAccess;
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let results = manager.for_each_service(
+    ///     &["service_a", "service_b"],
+    ///     ServiceAccess::QUERY_STATUS | ServiceAccess::STOP,
+    ///     |service| service.stop(),
+    /// );
+    /// for result in results {
+    ///     match result.result {
+    ///         Ok(_) => println!("stopped {:?} in {:?}", result.name, result.elapsed),
+    ///         Err(e) => println!("failed to stop {:?}: {}", result.name, e),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn for_each_service<T>(
+        &self,
+        names: &[impl AsRef<OsStr>],
+        access: ServiceAccess,
+        mut op: impl FnMut(&Service) -> Result<T>,
+    ) -> Vec<ServiceOpResult<T>> {
+        names
+            .iter()
+            .map(|name| {
+                let start = Instant::now();
+                let result = self
+                    .open_service(name, access)
+                    .and_then(|service| op(&service));
+                ServiceOpResult {
+                    name: name.as_ref().to_owned(),
+                    result,
+                    elapsed: start.elapsed(),
+                }
+            })
+            .collect()
+    }
+
+    /// Private helper that audits a single service for [`ServiceManager::audit_services`].
+    fn audit_service(&self, name: &OsStr) -> ServiceAudit {
+        let service = self.open_service(name, ServiceAccess::QUERY_CONFIG).ok();
+        let config = service
+            .as_ref()
+            .and_then(|service| service.query_config().ok());
+        let sid_type = service
+            .as_ref()
+            .and_then(|service| service.get_config_service_sid_info().ok());
+        ServiceAudit {
+            name: name.to_owned(),
+            account_name: config
+                .as_ref()
+                .and_then(|config| config.account_name.clone()),
+            start_type: config.as_ref().map(|config| config.start_type),
+            sid_type,
+            has_unquoted_path_vulnerability: config
+                .as_ref()
+                .map(|config| config.has_unquoted_path_vulnerability()),
+        }
+    }
+
+    /// Compute the order in which to start the given services so that every service is started
+    /// after the services it depends on.
+    ///
+    /// The dependencies are queried from the system and followed transitively, so the returned
+    /// plan also contains the services that the given services depend on, each listed once.
+    /// Dependencies on load ordering groups are not resolved and are ignored. Service names are
+    /// compared ignoring the ASCII case.
+    ///
+    /// Required permission on each service: [`ServiceAccess::QUERY_CONFIG`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DependencyCycle`] if the dependencies form a cycle.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// for name in manager.compute_start_order(&["my_frontend", "my_backend"])? {
+    ///     println!("start {}", name.to_string_lossy());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compute_start_order(&self, names: &[impl AsRef<OsStr>]) -> Result<Vec<OsString>> {
+        topological_order(names, |name| {
+            let config = self
+                .open_service(name, ServiceAccess::QUERY_CONFIG)?
+                .query_config()?;
+            Ok(config
+                .dependencies
+                .into_iter()
+                .filter_map(|dependency| match dependency {
+                    ServiceDependency::Service(name) => Some(name),
+                    ServiceDependency::Group(_) => None,
+                })
+                .collect())
+        })
+    }
+
+    /// Private helper that enumerates the services chunk by chunk, reusing the `buffer` for each
+    /// chunk and passing every entry to `on_entry`.
+    ///
+    /// The string pointers of the entries passed to `on_entry` point into the `buffer` and are
+    /// only valid for the duration of the call.
+    fn enumerate_raw(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+        buffer: &mut Vec<u8>,
+        mut on_entry: impl FnMut(Services::ENUM_SERVICE_STATUS_PROCESSW) -> Result<()>,
+    ) -> Result<()> {
+        let mut resume_handle: u32 = 0;
+        while self.enumerate_chunk(
+            service_type,
+            state,
+            buffer,
+            &mut resume_handle,
+            &mut on_entry,
+        )? {}
+        Ok(())
+    }
+
+    /// Private helper that reads a single chunk of services, starting at `resume_handle`, and
+    /// passes every entry to `on_entry`.
+    ///
+    /// Returns `true` if there are more services to read.
+    fn enumerate_chunk(
+        &self,
+        service_type: ServiceType,
+        state: ServiceStateFilter,
+        buffer: &mut Vec<u8>,
+        resume_handle: &mut u32,
+        mut on_entry: impl FnMut(Services::ENUM_SERVICE_STATUS_PROCESSW) -> Result<()>,
+    ) -> Result<bool> {
+        let mut bytes_needed: u32 = 0;
+        let mut services_returned: u32 = 0;
+        let success = unsafe {
+            Services::EnumServicesStatusExW(
+                self.manager_handle.raw_handle(),
+                Services::SC_ENUM_PROCESS_INFO,
+                service_type.bits(),
+                state.to_raw(),
+                buffer.as_mut_ptr(),
+                u32::try_from(buffer.len()).expect("size must fit in u32"),
+                &mut bytes_needed,
+                &mut services_returned,
+                resume_handle,
+                ptr::null(), // group name
+            )
+        };
+
+        let has_more_data = if success == 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() != Some(ERROR_MORE_DATA as i32) {
+                return Err(Error::winapi(error));
+            }
+            true
+        } else {
+            false
+        };
+
+        let raw_entries = buffer.as_ptr() as *const Services::ENUM_SERVICE_STATUS_PROCESSW;
+        for i in 0..services_returned as usize {
+            on_entry(unsafe { ptr::read_unaligned(raw_entries.add(i)) })?;
+        }
+
+        // Grow the buffer when it could not fit a single entry, otherwise keep on reading
+        // the remaining entries in chunks of the current size.
+        if has_more_data && (services_returned == 0 || buffer.len() < MIN_ENUM_BUFFER_SIZE) {
+            let new_len = cmp::max(bytes_needed as usize, MIN_ENUM_BUFFER_SIZE);
+            buffer.resize(cmp::min(new_len, MAX_ENUM_BUFFER_SIZE), 0);
+        }
+
+        Ok(has_more_data)
+    }
+}
+
+/// Order `roots` and their transitive dependencies, as returned by `dependencies_of`, so that
+/// every name comes after its dependencies.
+fn topological_order<F>(
+    roots: &[impl AsRef<OsStr>],
+    mut dependencies_of: F,
+) -> Result<Vec<OsString>>
+where
+    F: FnMut(&OsStr) -> Result<Vec<OsString>>,
+{
+    // Maps the lowercase name to whether all of its dependencies have been ordered.
+    let mut visited = HashMap::new();
+    let mut path = Vec::new();
+    let mut order = Vec::new();
+    for root in roots {
+        visit_dependencies(
+            root.as_ref(),
+            &mut dependencies_of,
+            &mut visited,
+            &mut path,
+            &mut order,
+        )?;
+    }
+    Ok(order)
+}
+
+/// Private helper for [`topological_order`] that orders the dependencies of `name` and then
+/// `name` itself, using a depth-first search.
+fn visit_dependencies<F>(
+    name: &OsStr,
+    dependencies_of: &mut F,
+    visited: &mut HashMap<OsString, bool>,
+    path: &mut Vec<OsString>,
+    order: &mut Vec<OsString>,
+) -> Result<()>
+where
+    F: FnMut(&OsStr) -> Result<Vec<OsString>>,
+{
+    let key = name.to_ascii_lowercase();
+    match visited.get(&key) {
+        Some(true) => return Ok(()),
+        Some(false) => {
+            let start = path
+                .iter()
+                .position(|visiting: &OsString| visiting.eq_ignore_ascii_case(name))
+                .unwrap_or(0);
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name.to_owned());
+            return Err(Error::DependencyCycle(cycle));
+        }
+        None => {}
+    }
+
+    visited.insert(key.clone(), false);
+    path.push(name.to_owned());
+    for dependency in dependencies_of(name)? {
+        visit_dependencies(&dependency, dependencies_of, visited, path, order)?;
+    }
+    path.pop();
+    visited.insert(key, true);
+    order.push(name.to_owned());
+    Ok(())
+}
+
+/// An iterator over services that reads them from the system one chunk at a time.
+///
+/// See [`ServiceManager::enumerate_services_iter`].
+pub struct ServiceEntries<'a> {
+    manager: &'a ServiceManager,
+    service_type: ServiceType,
+    state: ServiceStateFilter,
+    buffer: Vec<u8>,
+    resume_handle: u32,
+    has_more: bool,
+    chunk: std::vec::IntoIter<ServiceEntry>,
+    seen_names: HashSet<OsString>,
+}
+
+impl<'a> Iterator for ServiceEntries<'a> {
+    type Item = Result<ServiceEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            for entry in self.chunk.by_ref() {
+                if self.seen_names.insert(entry.name.to_ascii_lowercase()) {
+                    return Some(Ok(entry));
+                }
+            }
+            if !self.has_more {
+                return None;
+            }
+
+            let mut entries = Vec::new();
+            let result = self.manager.enumerate_chunk(
+                self.service_type,
+                self.state,
+                &mut self.buffer,
+                &mut self.resume_handle,
+                |raw_entry| {
+                    entries.push(unsafe { ServiceEntry::from_raw(raw_entry) }?);
+                    Ok(())
+                },
+            );
+            match result {
+                Ok(has_more) => {
+                    self.has_more = has_more;
+                    self.chunk = entries.into_iter();
+                }
+                Err(e) => {
+                    // Do not retry a failed enumeration.
+                    self.has_more = false;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// A reusable service enumeration that keeps its allocations between refreshes.
+///
+/// This is useful for tools that poll the list of services periodically, where calling
+/// [`ServiceManager::enumerate_services`] would allocate a new buffer on every call.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::thread;
+/// use std::time::Duration;
+/// use windows_service::service::ServiceType;
+/// use windows_service::service_manager::{
+///     ServiceManager, ServiceManagerAccess, ServiceStateFilter,
+/// };
+///
+/// # fn main() -> windows_service::Result<()> {
+/// let manager =
+///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+/// let mut enumerator =
+///     manager.service_enumerator(ServiceType::OWN_PROCESS, ServiceStateFilter::Active);
+/// loop {
+///     let services = enumerator.refresh()?;
+///     println!("{} running services", services.len());
+///     thread::sleep(Duration::from_secs(5));
+/// }
+/// # }
+/// ```
+pub struct ServiceEnumerator<'a> {
+    manager: &'a ServiceManager,
+    service_type: ServiceType,
+    state: ServiceStateFilter,
+    buffer: Vec<u8>,
+    entries: Vec<ServiceEntry>,
+    seen_names: HashSet<OsString>,
+}
+
+impl<'a> ServiceEnumerator<'a> {
+    /// Enumerate the services again and return the up to date list.
+    ///
+    /// The same ordering and duplicate guarantees as for [`ServiceManager::enumerate_services`]
+    /// apply. If the enumeration fails, the previous list is discarded.
+    pub fn refresh(&mut self) -> Result<&[ServiceEntry]> {
+        self.entries.clear();
+        self.seen_names.clear();
+
+        let entries = &mut self.entries;
+        let seen_names = &mut self.seen_names;
+        let result = self.manager.enumerate_raw(
+            self.service_type,
+            self.state,
+            &mut self.buffer,
+            |raw_entry| {
+                let entry = unsafe { ServiceEntry::from_raw(raw_entry) }?;
+                if seen_names.insert(entry.name.to_ascii_lowercase()) {
+                    entries.push(entry);
+                }
+                Ok(())
+            },
+        );
+        if let Err(e) = result {
+            self.entries.clear();
+            return Err(e);
+        }
+        Ok(&self.entries)
+    }
+
+    /// Returns the services found by the last [`ServiceEnumerator::refresh`], or an empty list if
+    /// it failed.
+    pub fn entries(&self) -> &[ServiceEntry] {
+        &self.entries
+    }
+}
+
+/// The initial size of data buffer used by EnumServicesStatusExW.
+const MIN_ENUM_BUFFER_SIZE: usize = 16 * 1024;
+
+/// As per docs, the maximum size of data buffer used by EnumServicesStatusExW is 256K.
+const MAX_ENUM_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Check whether the `actual` failure actions of a service satisfy the `expected` ones.
+///
+/// Only the fields that `expected` sets are compared, since `None` leaves them unchanged when
+/// applied. The reset period is ignored along with the actions. A missing reboot message,
+/// command or list of actions is the same as an empty one.
+fn failure_actions_match(expected: &ServiceFailureActions, actual: &ServiceFailureActions) -> bool {
+    fn text_matches(expected: &Option<OsString>, actual: &Option<OsString>) -> bool {
+        match expected {
+            Some(expected) => actual.as_deref().unwrap_or_default() == expected.as_os_str(),
+            None => true,
+        }
+    }
+
+    let actions_match = match &expected.actions {
+        Some(expected_actions) => {
+            actual.actions.as_deref().unwrap_or_default() == expected_actions.as_slice()
+                && actual.reset_period == expected.reset_period
+        }
+        None => true,
+    };
+    actions_match
+        && text_matches(&expected.reboot_msg, &actual.reboot_msg)
+        && text_matches(&expected.command, &actual.command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::{
+        ServiceAction, ServiceActionType, ServiceControlAccept, ServiceExitCode,
+        ServiceFailureResetPeriod, ServiceState,
+    };
+    use std::time::Duration;
+
+    fn entry(name: &str) -> ServiceEntry {
+        ServiceEntry {
+            name: OsString::from(name),
+            display_name: OsString::from(name),
+            status: ServiceStatus {
+                service_type: ServiceType::OWN_PROCESS,
+                current_state: ServiceState::Stopped,
+                controls_accepted: ServiceControlAccept::empty(),
+                exit_code: ServiceExitCode::NO_ERROR,
+                checkpoint: 0,
+                wait_hint: Duration::default(),
+                process_id: None,
+            },
+            service_flags: ServiceFlags::empty(),
+        }
+    }
+
+    #[test]
+    fn test_sorted_by_name_ignores_case() {
+        let sorted = sorted_by_name(vec![entry("WinRM"), entry("bits"), entry("Audiosrv")]);
+        let names: Vec<&OsStr> = sorted.iter().map(|entry| entry.name.as_os_str()).collect();
+        assert_eq!(names, ["Audiosrv", "bits", "WinRM"]);
+    }
+
+    #[test]
+    fn test_service_entry_from_raw_copies_strings() {
+        let mut buffer = WideCString::from_str("my_service")
+            .unwrap()
+            .into_vec_with_nul();
+        let display_name_offset = buffer.len();
+        buffer.extend(
+            WideCString::from_str("My service")
+                .unwrap()
+                .into_vec_with_nul(),
+        );
+
+        let mut raw_entry = unsafe { std::mem::zeroed::<Services::ENUM_SERVICE_STATUS_PROCESSW>() };
+        raw_entry.lpServiceName = buffer.as_mut_ptr();
+        raw_entry.lpDisplayName = unsafe { buffer.as_mut_ptr().add(display_name_offset) };
+        raw_entry.ServiceStatusProcess.dwCurrentState = Services::SERVICE_STOPPED;
+        let entry = unsafe { ServiceEntry::from_raw(raw_entry) }.unwrap();
+
+        // Overwrite and free the buffer the raw entry points into.
+        buffer.iter_mut().for_each(|c| *c = 0);
+        drop(buffer);
+
+        assert_eq!(entry.name, OsString::from("my_service"));
+        assert_eq!(entry.display_name, OsString::from("My service"));
+    }
+
+    #[test]
+    fn test_service_entry_from_raw_null_display_name() {
+        let mut name = WideCString::from_str("my_service")
+            .unwrap()
+            .into_vec_with_nul();
+        let mut raw_entry = unsafe { std::mem::zeroed::<Services::ENUM_SERVICE_STATUS_PROCESSW>() };
+        raw_entry.lpServiceName = name.as_mut_ptr();
+        raw_entry.ServiceStatusProcess.dwCurrentState = Services::SERVICE_STOPPED;
+        let entry = unsafe { ServiceEntry::from_raw(raw_entry) }.unwrap();
+
+        assert_eq!(entry.name, OsString::from("my_service"));
+        assert!(entry.display_name.is_empty());
+    }
+
+    fn dependencies_from<'a>(
+        graph: &'a [(&'a str, &'a [&'a str])],
+    ) -> impl FnMut(&OsStr) -> Result<Vec<OsString>> + 'a {
+        move |name| {
+            Ok(graph
+                .iter()
+                .find(|(service, _)| OsStr::new(service).eq_ignore_ascii_case(name))
+                .map(|(_, dependencies)| dependencies.iter().map(OsString::from).collect())
+                .unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn test_topological_order() {
+        let graph: &[(&str, &[&str])] = &[
+            ("frontend", &["backend", "Tcpip"]),
+            ("backend", &["database", "tcpip"]),
+            ("database", &[]),
+        ];
+        let order = topological_order(&["frontend", "database"], dependencies_from(graph)).unwrap();
+        assert_eq!(order, vec!["database", "tcpip", "backend", "frontend"]);
+    }
+
+    #[test]
+    fn test_topological_order_cycle() {
+        let graph: &[(&str, &[&str])] = &[("a", &["b"]), ("b", &["c"]), ("c", &["A"])];
+        match topological_order(&["a"], dependencies_from(graph)) {
+            Err(Error::DependencyCycle(cycle)) => assert_eq!(cycle, vec!["a", "b", "c", "A"]),
+            other => panic!("expected a dependency cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_failure_actions_match_only_set_fields() {
+        let actual = ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(86400)),
+            reboot_msg: None,
+            command: Some(OsString::from("notify.exe")),
+            actions: Some(vec![ServiceAction {
+                action_type: ServiceActionType::Restart,
+                delay: Duration::from_secs(5),
+            }]),
+        };
+
+        let mut expected = ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::Never,
+            reboot_msg: None,
+            command: None,
+            actions: None,
+        };
+        assert!(failure_actions_match(&expected, &actual));
+
+        expected.reboot_msg = Some(OsString::new());
+        assert!(failure_actions_match(&expected, &actual));
+        expected.command = Some(OsString::from("other.exe"));
+        assert!(!failure_actions_match(&expected, &actual));
+        expected.command = Some(OsString::from("notify.exe"));
+        assert!(failure_actions_match(&expected, &actual));
+
+        expected.actions = actual.actions.clone();
+        assert!(!failure_actions_match(&expected, &actual));
+        expected.reset_period = actual.reset_period;
+        assert!(failure_actions_match(&expected, &actual));
+    }
+
+    #[test]
+    fn test_service_entry_ref_from_raw() {
+        let mut name = WideCString::from_str("Tcpip").unwrap().into_vec_with_nul();
+        let mut raw = unsafe { std::mem::zeroed::<Services::ENUM_SERVICE_STATUS_PROCESSW>() };
+        raw.lpServiceName = name.as_mut_ptr();
+        raw.ServiceStatusProcess.dwCurrentState = Services::SERVICE_RUNNING;
+
+        let entry_ref = unsafe { ServiceEntryRef::from_raw(raw) }.unwrap();
+        assert_eq!(entry_ref.name.to_os_string(), OsString::from("Tcpip"));
+        assert!(entry_ref.display_name.is_empty());
+
+        let entry = entry_ref.to_entry();
+        assert_eq!(entry.name, OsString::from("Tcpip"));
+        assert_eq!(entry.display_name, OsString::new());
+        assert_eq!(entry.status.current_state, ServiceState::Running);
+    }
+
+    #[test]
+    fn test_apply_manifest_rejects_invalid_info() {
+        // Any Win32 call made with the null handle fails with a `Winapi` error, so the
+        // `RelativeExecutablePath` error shows that the manifest is rejected before that.
+        let manager = ServiceManager {
+            manager_handle: Arc::new(unsafe { ScHandle::new(ptr::null_mut()) }),
+        };
+        let manifest = ServiceManifest::new(ServiceInfo {
+            name: OsString::from("test"),
+            display_name: OsString::from("Test"),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::OnDemand,
+            error_control: crate::service::ServiceErrorControl::Normal,
+            executable_path: "service.exe".into(),
+            launch_arguments: vec![],
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+        });
+        assert!(matches!(
+            manager.apply_manifest(&manifest),
+            Err(Error::RelativeExecutablePath(_))
+        ));
+        assert!(matches!(
+            manager.reconcile_manifest(&manifest),
+            Err(Error::RelativeExecutablePath(_))
+        ));
+    }
 }